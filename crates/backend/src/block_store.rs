@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::db::ServerDBError;
+
+/// Content-addressed block storage, keyed by hash -- the hash is the object key, so
+/// storing the same block twice is a no-op rather than a duplicate write, and blocks
+/// are immutable by construction.
+pub trait BlockStore: Send + Sync {
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, ServerDBError>;
+    fn put(&self, hash: &str, data: Vec<u8>) -> Result<(), ServerDBError>;
+    fn has(&self, hash: &str) -> Result<bool, ServerDBError>;
+    fn delete(&self, hash: &str) -> Result<(), ServerDBError>;
+}
+
+/// Default driver: one file per block under `base_dir`, sharded by the first two hex
+/// characters of the hash so a single directory doesn't accumulate millions of
+/// entries.
+pub struct FilesystemBlockStore {
+    base_dir: PathBuf,
+}
+
+impl FilesystemBlockStore {
+    pub fn new(base_dir: PathBuf) -> Result<FilesystemBlockStore, ServerDBError> {
+        fs::create_dir_all(&base_dir).map_err(|e| {
+            ServerDBError::Fundamental(format!("Cannot create block store dir: {:?}", e))
+        })?;
+        Ok(FilesystemBlockStore { base_dir })
+    }
+
+    fn path_for(&self, hash: &str) -> PathBuf {
+        let shard = &hash[..hash.len().min(2)];
+        self.base_dir.join(shard).join(hash)
+    }
+}
+
+impl BlockStore for FilesystemBlockStore {
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, ServerDBError> {
+        let path = self.path_for(hash);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read(&path)
+            .map(Some)
+            .map_err(|e| ServerDBError::Error(format!("Cannot read block {}: {:?}", hash, e)))
+    }
+
+    fn put(&self, hash: &str, data: Vec<u8>) -> Result<(), ServerDBError> {
+        let path = self.path_for(hash);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                ServerDBError::Error(format!("Cannot create block shard dir: {:?}", e))
+            })?;
+        }
+        fs::write(&path, data)
+            .map_err(|e| ServerDBError::Error(format!("Cannot write block {}: {:?}", hash, e)))
+    }
+
+    fn has(&self, hash: &str) -> Result<bool, ServerDBError> {
+        Ok(self.path_for(hash).exists())
+    }
+
+    fn delete(&self, hash: &str) -> Result<(), ServerDBError> {
+        let path = self.path_for(hash);
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| {
+                ServerDBError::Error(format!("Cannot delete block {}: {:?}", hash, e))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub region: String,
+}
+
+/// S3-compatible object-store driver (also speaks the Garage S3 API surface) --
+/// blocks live as objects keyed by hash in `bucket`, so a deployment can offload
+/// gigabytes of block data to cheap object storage while the commit graph stays in
+/// the relational DB.
+pub struct S3BlockStore {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3BlockStore {
+    pub async fn new(config: S3Config) -> S3BlockStore {
+        let credentials = aws_sdk_s3::config::Credentials::new(
+            config.access_key,
+            config.secret_key,
+            None,
+            None,
+            "timeline-backend",
+        );
+        let s3_config = aws_sdk_s3::Config::builder()
+            .endpoint_url(config.endpoint)
+            .region(aws_sdk_s3::config::Region::new(config.region))
+            .credentials_provider(credentials)
+            .force_path_style(true)
+            .build();
+
+        S3BlockStore {
+            client: aws_sdk_s3::Client::from_conf(s3_config),
+            bucket: config.bucket,
+        }
+    }
+}
+
+impl BlockStore for S3BlockStore {
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, ServerDBError> {
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let hash = hash.to_string();
+
+        futures::executor::block_on(async move {
+            match client.get_object().bucket(bucket).key(hash).send().await {
+                Ok(output) => {
+                    let bytes = output.body.collect().await.map_err(|e| {
+                        ServerDBError::Error(format!("Cannot read object body: {:?}", e))
+                    })?;
+                    Ok(Some(bytes.into_bytes().to_vec()))
+                }
+                Err(aws_sdk_s3::error::SdkError::ServiceError(e)) if e.err().is_no_such_key() => {
+                    Ok(None)
+                }
+                Err(e) => Err(ServerDBError::Error(format!("Cannot get object: {:?}", e))),
+            }
+        })
+    }
+
+    fn put(&self, hash: &str, data: Vec<u8>) -> Result<(), ServerDBError> {
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let hash = hash.to_string();
+
+        futures::executor::block_on(async move {
+            client
+                .put_object()
+                .bucket(bucket)
+                .key(hash)
+                .body(data.into())
+                .send()
+                .await
+                .map_err(|e| ServerDBError::Error(format!("Cannot put object: {:?}", e)))?;
+            Ok(())
+        })
+    }
+
+    fn has(&self, hash: &str) -> Result<bool, ServerDBError> {
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let hash = hash.to_string();
+
+        futures::executor::block_on(async move {
+            match client.head_object().bucket(bucket).key(hash).send().await {
+                Ok(_) => Ok(true),
+                Err(aws_sdk_s3::error::SdkError::ServiceError(e)) if e.err().is_not_found() => {
+                    Ok(false)
+                }
+                Err(e) => Err(ServerDBError::Error(format!("Cannot head object: {:?}", e))),
+            }
+        })
+    }
+
+    fn delete(&self, hash: &str) -> Result<(), ServerDBError> {
+        let client = self.client.clone();
+        let bucket = self.bucket.clone();
+        let hash = hash.to_string();
+
+        futures::executor::block_on(async move {
+            client
+                .delete_object()
+                .bucket(bucket)
+                .key(hash)
+                .send()
+                .await
+                .map_err(|e| ServerDBError::Error(format!("Cannot delete object: {:?}", e)))?;
+            Ok(())
+        })
+    }
+}
+
+/// Picks a driver from environment config: `BLOCK_STORE_BACKEND=s3` opts into the
+/// object-store driver (reading endpoint/bucket/credentials from the
+/// `BLOCK_STORE_S3_*` variables below), anything else -- including unset -- falls
+/// back to local-disk storage under `<base_dir>/blocks`.
+pub fn open_block_store(base_dir: &Path) -> Result<Box<dyn BlockStore>, ServerDBError> {
+    match std::env::var("BLOCK_STORE_BACKEND").as_deref() {
+        Ok("s3") => {
+            let config = S3Config {
+                endpoint: std::env::var("BLOCK_STORE_S3_ENDPOINT").map_err(|_| {
+                    ServerDBError::Fundamental("BLOCK_STORE_S3_ENDPOINT not set".to_owned())
+                })?,
+                bucket: std::env::var("BLOCK_STORE_S3_BUCKET").map_err(|_| {
+                    ServerDBError::Fundamental("BLOCK_STORE_S3_BUCKET not set".to_owned())
+                })?,
+                access_key: std::env::var("BLOCK_STORE_S3_ACCESS_KEY").map_err(|_| {
+                    ServerDBError::Fundamental("BLOCK_STORE_S3_ACCESS_KEY not set".to_owned())
+                })?,
+                secret_key: std::env::var("BLOCK_STORE_S3_SECRET_KEY").map_err(|_| {
+                    ServerDBError::Fundamental("BLOCK_STORE_S3_SECRET_KEY not set".to_owned())
+                })?,
+                region: std::env::var("BLOCK_STORE_S3_REGION")
+                    .unwrap_or_else(|_| "us-east-1".to_owned()),
+            };
+
+            Ok(Box::new(futures::executor::block_on(S3BlockStore::new(
+                config,
+            ))))
+        }
+        _ => Ok(Box::new(FilesystemBlockStore::new(base_dir.join("blocks"))?)),
+    }
+}
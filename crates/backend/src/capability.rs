@@ -0,0 +1,103 @@
+use actix_web::HttpRequest;
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::login::REDIS_URL;
+
+/// Header clients send their capability token under.
+const CAPABILITY_HEADER: &str = "X-Capability-Token";
+
+fn capability_key(token: &str) -> String {
+    format!("capability:{}", token)
+}
+
+/// What a capability token lets its holder do against one project. Declared in
+/// ascending order of trust, so `Scope::satisfies` can compare variants directly --
+/// `Admin` covers everything `Append` does, and `Append` covers everything `Read`
+/// does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Scope {
+    /// Clone/pull a project's commits and blocks.
+    Read,
+    /// Push new commits and blocks via `/v1/sync`.
+    Append,
+    /// Reserved for operations that rewrite history rather than append to it, e.g. a
+    /// future endpoint that force-moves a branch tip. No such endpoint exists yet, so
+    /// nothing currently requires this scope -- it's here so issuing a token doesn't
+    /// need a second migration once one does.
+    Admin,
+}
+
+impl Scope {
+    fn satisfies(self, required: Scope) -> bool {
+        self >= required
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CapabilityRecord {
+    project_id: String,
+    scope: Scope,
+}
+
+/// Mints a capability token granting `scope` on `project_id`, storing it in Redis
+/// with no expiry -- unlike a login session, a capability token is meant to be handed
+/// to a CI job or a teammate's client and live until explicitly revoked.
+pub fn issue_capability(project_id: &str, scope: Scope) -> Result<String, redis::RedisError> {
+    let token = Uuid::new_v4().to_string();
+
+    let client = redis::Client::open(REDIS_URL).unwrap();
+    let mut conn = client.get_connection()?;
+
+    let record = CapabilityRecord {
+        project_id: project_id.to_owned(),
+        scope,
+    };
+    let serialized = serde_json::to_string(&record).expect("Cannot serialize capability record");
+    conn.set(capability_key(&token), serialized)?;
+
+    Ok(token)
+}
+
+/// Deletes a capability token, revoking whatever it granted.
+pub fn revoke_capability(token: &str) -> Result<(), redis::RedisError> {
+    let client = redis::Client::open(REDIS_URL).unwrap();
+    let mut conn = client.get_connection()?;
+    conn.del(capability_key(token))
+}
+
+/// `Ok(())` if the request's `X-Capability-Token` header names a token granting at
+/// least `required` on `project_id`; `Err` with the response to return otherwise.
+pub fn authorize(
+    req: &HttpRequest,
+    project_id: &str,
+    required: Scope,
+) -> Result<(), actix_web::HttpResponse> {
+    let token = req
+        .headers()
+        .get(CAPABILITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| {
+            actix_web::HttpResponse::Unauthorized().json("Missing X-Capability-Token header")
+        })?;
+
+    let client = redis::Client::open(REDIS_URL).unwrap();
+    let mut conn = client
+        .get_connection()
+        .map_err(|_| actix_web::HttpResponse::InternalServerError().json("Internal error"))?;
+
+    let raw: Option<String> = conn
+        .get(capability_key(token))
+        .map_err(|_| actix_web::HttpResponse::InternalServerError().json("Internal error"))?;
+
+    let record: CapabilityRecord = raw
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .ok_or_else(|| actix_web::HttpResponse::Unauthorized().json("Invalid capability token"))?;
+
+    if record.project_id != project_id || !record.scope.satisfies(required) {
+        return Err(actix_web::HttpResponse::Forbidden().json("Capability does not permit this"));
+    }
+
+    Ok(())
+}
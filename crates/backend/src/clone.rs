@@ -1,18 +1,28 @@
 use std::collections::{HashMap, HashSet};
 
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use timeline_lib::{
     api::common::parse_hash_list,
     db::structs::{BlockRecord, Commit},
-    exchange::structs::{encode_exchange, Exchange},
+    exchange::{
+        bloom_filter::BloomFilter,
+        structs::{encode_exchange, Exchange},
+    },
 };
 
 use crate::{
+    capability::{authorize, Scope},
     db::{open_db, read_blocks, read_commits_with_project_id, ServerDBError, DB},
+    metrics::{BLOCKS_DEDUPLICATED, ENDPOINT_LATENCY_SECONDS, ENDPOINT_REQUESTS, EXCHANGE_BYTES},
+    sync::bloom_filter_from_request,
     utils::e500,
 };
 
-fn prepare_exchange_response(db: &DB, project_id: &str) -> Result<Exchange, ServerDBError> {
+fn prepare_exchange_response(
+    db: &DB,
+    project_id: &str,
+    peer_has: Option<&BloomFilter>,
+) -> Result<Exchange, ServerDBError> {
     let mut all_commits: Vec<Commit> = vec![];
     let mut block_hashes: HashSet<String> = HashSet::new();
 
@@ -28,6 +38,12 @@ fn prepare_exchange_response(db: &DB, project_id: &str) -> Result<Exchange, Serv
         }
     }
 
+    if let Some(filter) = peer_has {
+        let before = block_hashes.len();
+        block_hashes.retain(|hash| !filter.might_contain(hash));
+        BLOCKS_DEDUPLICATED.inc_by((before - block_hashes.len()) as u64);
+    }
+
     let mut all_blocks: HashMap<String, BlockRecord> = HashMap::new();
     for block in read_blocks(db, block_hashes.into_iter().collect())? {
         all_blocks.insert(block.hash.clone(), block);
@@ -41,17 +57,41 @@ fn prepare_exchange_response(db: &DB, project_id: &str) -> Result<Exchange, Serv
     })
 }
 
-pub async fn clone_project(path: web::Path<(String,)>) -> Result<HttpResponse, actix_web::Error> {
+pub async fn clone_project(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ENDPOINT_REQUESTS.with_label_values(&["clone_project"]).inc();
+    let timer = ENDPOINT_LATENCY_SECONDS
+        .with_label_values(&["clone_project"])
+        .start_timer();
+
     let (project_id,) = path.into_inner();
-    let db_result = open_db("/Users/bertalankormendy/Developer/timeline-backend/timeline-backend");
-    if let Err(error) = db_result {
-        println!("{:?}", error);
-        return Err(e500(format!("{:?}", error)));
+
+    if let Err(response) = authorize(&req, &project_id, Scope::Read) {
+        return Ok(response);
     }
-    let db = db_result.unwrap();
 
-    let exchange = prepare_exchange_response(&db, &project_id).map_err(e500)?;
-    let response_bytes = encode_exchange(&exchange).map_err(e500)?;
+    let peer_has = bloom_filter_from_request(&req);
+
+    // Same rationale as `v1_sync`: opening the DB and walking every commit for this
+    // project are blocking operations, so they run on actix-web's blocking threadpool
+    // rather than the async reactor.
+    let response_bytes = web::block(move || -> Result<Vec<u8>, String> {
+        let db = open_db("/Users/bertalankormendy/Developer/timeline-backend/timeline-backend")
+            .map_err(|e| format!("{:?}", e))?;
+
+        let exchange = prepare_exchange_response(&db, &project_id, peer_has.as_ref())
+            .map_err(|e| format!("{:?}", e))?;
+        let response_bytes = encode_exchange(&exchange).map_err(|e| format!("{:?}", e))?;
+        EXCHANGE_BYTES.inc_by(response_bytes.len() as u64);
+
+        Ok(response_bytes)
+    })
+    .await
+    .map_err(e500)?
+    .map_err(e500)?;
 
+    timer.observe_duration();
     Ok(HttpResponse::Ok().body(response_bytes))
 }
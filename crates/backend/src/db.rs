@@ -1,6 +1,123 @@
 use std::{fmt::Display, path::Path};
 
-use timeline_lib::db::structs::{BlockRecord, Commit};
+use rocksdb::{ColumnFamilyDescriptor, Options};
+use timeline_lib::db::structs::{BlockRecord, Commit, ShortCommit};
+
+use crate::{
+    block_store::{open_block_store, BlockStore},
+    metrics::{COMMITS_READ, COMMITS_WRITTEN, DB_OPEN_FAILURES},
+};
+
+/// Column family holding the working-directory snapshot blob each commit carries
+/// (`commit.blocks`), keyed by bare commit hash. Before this, these lived in `kv`'s
+/// default column family under a stringly-typed `working-dir-{:?}` prefix.
+const CF_WORKING_DIR: &str = "working_dir";
+/// Reserved for a future server-side block store backed directly by `kv` rather than
+/// `BlockStore`'s filesystem/S3 drivers -- not written to yet.
+const CF_BLOCKS: &str = "blocks";
+/// Reserved for a future server-side refcount GC mirroring the client's
+/// `block-rc-{hash}` scheme -- not written to yet.
+const CF_BLOCK_REFCOUNTS: &str = "block_refcounts";
+/// Storage-format bookkeeping: just the version key `run_pending_migrations` compares
+/// against, kept separate so it's never accidentally iterated over with real data.
+const CF_META: &str = "meta";
+
+const STORAGE_FORMAT_VERSION_KEY: &str = "storage_format_version";
+
+/// Current on-disk layout of `kv`. Bump this and append a migration to `MIGRATIONS`
+/// whenever a change touches its column families or key formats.
+const CURRENT_STORAGE_FORMAT_VERSION: i64 = 1;
+
+type Migration = fn(&DB) -> Result<(), ServerDBError>;
+
+/// Ordered, oldest first; the entry at index `i` produces version `i + 1` -- index `0`
+/// brings a pre-CF store to version `1`. Append only; never reorder or remove an
+/// entry that's already shipped.
+const MIGRATIONS: &[Migration] = &[migrate_v0_default_cf_working_dir_into_cf];
+
+/// Moves every `working-dir-{:?}`-prefixed key out of the default column family
+/// (where pre-CF stores kept it) into [`CF_WORKING_DIR`] under its bare commit hash.
+fn migrate_v0_default_cf_working_dir_into_cf(db: &DB) -> Result<(), ServerDBError> {
+    let working_dir_cf = db.kv.cf_handle(CF_WORKING_DIR).ok_or_else(|| {
+        ServerDBError::Fundamental("Missing working_dir column family".to_owned())
+    })?;
+
+    let prefix = "working-dir-";
+    let mut migrated_keys: Vec<Vec<u8>> = Vec::new();
+
+    for item in db.kv.iterator(rocksdb::IteratorMode::Start) {
+        let (key, value) = item.map_err(|e| {
+            ServerDBError::Error(format!("Cannot scan default column family: {:?}", e))
+        })?;
+
+        // Pre-CF keys were written as `working-dir-{:?}`, so the hash is still wrapped
+        // in the quotes `Debug` added.
+        if let Some(hash) = String::from_utf8_lossy(&key)
+            .strip_prefix(prefix)
+            .map(|rest| rest.trim_matches('"'))
+        {
+            db.kv.put_cf(working_dir_cf, hash, &value).map_err(|e| {
+                ServerDBError::Error(format!("Cannot migrate working dir key: {:?}", e))
+            })?;
+            migrated_keys.push(key.to_vec());
+        }
+    }
+
+    for key in migrated_keys {
+        db.kv
+            .delete(key)
+            .map_err(|e| ServerDBError::Error(format!("Cannot delete migrated key: {:?}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// Runs every migration the on-disk storage format version hasn't seen yet, then
+/// stamps the current version -- so a store that's already current pays only the one
+/// `get_cf` this costs on every open.
+fn run_pending_migrations(db: &DB) -> Result<(), ServerDBError> {
+    let meta_cf = db
+        .kv
+        .cf_handle(CF_META)
+        .ok_or_else(|| ServerDBError::Fundamental("Missing meta column family".to_owned()))?;
+
+    let from_version = db
+        .kv
+        .get_cf(meta_cf, STORAGE_FORMAT_VERSION_KEY)
+        .map_err(|e| {
+            ServerDBError::Error(format!("Cannot read storage format version: {:?}", e))
+        })?
+        .map(|bytes| String::from_utf8_lossy(&bytes).parse::<i64>().unwrap_or(0))
+        .unwrap_or(0);
+
+    if from_version > CURRENT_STORAGE_FORMAT_VERSION {
+        return Err(ServerDBError::Fundamental(format!(
+            "Storage format version {} is newer than this server understands (current {})",
+            from_version, CURRENT_STORAGE_FORMAT_VERSION
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        let version = (index + 1) as i64;
+        if version > from_version {
+            migration(db)?;
+        }
+    }
+
+    if from_version < CURRENT_STORAGE_FORMAT_VERSION {
+        db.kv
+            .put_cf(
+                meta_cf,
+                STORAGE_FORMAT_VERSION_KEY,
+                CURRENT_STORAGE_FORMAT_VERSION.to_string(),
+            )
+            .map_err(|e| {
+                ServerDBError::Error(format!("Cannot write storage format version: {:?}", e))
+            })?;
+    }
+
+    Ok(())
+}
 
 #[derive(Debug)]
 pub enum ServerDBError {
@@ -22,9 +139,17 @@ impl Display for ServerDBError {
 pub struct DB {
     relational: rusqlite::Connection,
     kv: rocksdb::DB,
+    block_store: Box<dyn BlockStore>,
 }
 
 pub fn open_db(path: &str) -> Result<DB, ServerDBError> {
+    open_db_inner(path).map_err(|e| {
+        DB_OPEN_FAILURES.inc();
+        e
+    })
+}
+
+fn open_db_inner(path: &str) -> Result<DB, ServerDBError> {
     let relational_path = Path::new(path).join("commits.sqlite");
     let kv_path = Path::new(path).join("blobs.rocks");
 
@@ -41,31 +166,48 @@ pub fn open_db(path: &str) -> Result<DB, ServerDBError> {
         message TEXT,
         author TEXT,
         date INTEGER,
-        header BLOB
+        header BLOB,
+        author_pubkey BLOB,
+        signature BLOB
     )",
             [],
         )
         .map_err(|e| ServerDBError::Fundamental(format!("Cannot create commits table: {:?}", e)))?;
 
-    let rocks_db = rocksdb::DB::open_default(kv_path)
+    let mut db_opts = Options::default();
+    db_opts.create_if_missing(true);
+    db_opts.create_missing_column_families(true);
+    // A long-lived server process opens many SST files over its lifetime; cap it well
+    // under a typical `ulimit -n` rather than inheriting RocksDB's unbounded default.
+    db_opts.set_max_open_files(256);
+
+    let cf_opts = Options::default();
+    let cf_descriptors = vec![
+        ColumnFamilyDescriptor::new(CF_WORKING_DIR, cf_opts.clone()),
+        ColumnFamilyDescriptor::new(CF_BLOCKS, cf_opts.clone()),
+        ColumnFamilyDescriptor::new(CF_BLOCK_REFCOUNTS, cf_opts.clone()),
+        ColumnFamilyDescriptor::new(CF_META, cf_opts),
+    ];
+
+    let rocks_db = rocksdb::DB::open_cf_descriptors(&db_opts, kv_path, cf_descriptors)
         .map_err(|e| ServerDBError::Fundamental(format!("Cannot open RocksDB: {:?}", e)))?;
 
-    Ok(DB {
+    let block_store = open_block_store(Path::new(path))?;
+
+    let db = DB {
         relational: relational_db,
         kv: rocks_db,
-    })
-}
+        block_store,
+    };
+
+    run_pending_migrations(&db)?;
 
-#[inline]
-fn block_hash_key(key: &str) -> String {
-    format!("block-hash-{:?}", key)
+    Ok(db)
 }
 
 pub fn write_blocks(db: &DB, blocks: Vec<BlockRecord>) -> Result<(), ServerDBError> {
     for block in blocks {
-        db.kv
-            .put(block_hash_key(&block.hash), &block.data)
-            .map_err(|e| ServerDBError::Error(format!("Cannot write block: {:?}", e)))?;
+        db.block_store.put(&block.hash, block.data)?;
     }
 
     Ok(())
@@ -74,31 +216,45 @@ pub fn write_blocks(db: &DB, blocks: Vec<BlockRecord>) -> Result<(), ServerDBErr
 pub fn read_blocks(db: &DB, hashes: Vec<String>) -> Result<Vec<BlockRecord>, ServerDBError> {
     let mut result: Vec<BlockRecord> = Vec::new();
     for hash in hashes {
-        let block_data = db
-            .kv
-            .get(block_hash_key(&hash))
-            .map_err(|e| ServerDBError::Error(format!("Error reading block: {:?}", e)))?
+        let data = db
+            .block_store
+            .get(&hash)?
             .ok_or(ServerDBError::Error("No block with hash found".to_owned()))?;
 
-        result.push(BlockRecord {
-            hash,
-            data: block_data,
-        })
+        result.push(BlockRecord { hash, data })
     }
 
     Ok(result)
 }
 
+/// Filters `hashes` down to the ones the block store doesn't already have -- the
+/// server side of the want/have negotiation in `negotiate.rs`, reused for both the
+/// push direction (what can the client skip uploading) and the clone direction (what
+/// does the client still need to download).
+pub fn missing_block_hashes(db: &DB, hashes: Vec<String>) -> Result<Vec<String>, ServerDBError> {
+    let mut missing = Vec::new();
+    for hash in hashes {
+        if !db.block_store.has(&hash)? {
+            missing.push(hash);
+        }
+    }
+    Ok(missing)
+}
+
 pub fn write_commits(db: &DB, commits: Vec<Commit>) -> Result<(), ServerDBError> {
+    let working_dir_cf = db.kv.cf_handle(CF_WORKING_DIR).ok_or_else(|| {
+        ServerDBError::Fundamental("Missing working_dir column family".to_owned())
+    })?;
+
     for commit in commits {
         db.kv
-            .put(working_dir_key(&commit.hash), commit.blocks)
+            .put_cf(working_dir_cf, &commit.hash, &commit.blocks)
             .map_err(|_| ServerDBError::Error("Cannot write working dir blocks".to_owned()))?;
 
         let hash = commit.hash.clone();
 
         db.relational.execute(
-            "INSERT INTO commits (hash, prev_commit_hash, project_id, branch, message, author, date, header) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            "INSERT INTO commits (hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             (
                 commit.hash,
                 commit.prev_commit_hash,
@@ -108,24 +264,26 @@ pub fn write_commits(db: &DB, commits: Vec<Commit>) -> Result<(), ServerDBError>
                 commit.author,
                 commit.date,
                 commit.header,
+                commit.author_pubkey,
+                commit.signature,
             ),
         )
         .map_err(|e| ServerDBError::Error(format!("Cannot insert commit object: {:?}", e)))?;
 
+        COMMITS_WRITTEN.inc();
         println!("wrote commit with hash {}", hash);
     }
 
     Ok(())
 }
 
-#[inline]
-fn working_dir_key(key: &str) -> String {
-    format!("working-dir-{:?}", key)
-}
+fn get_blocks_by_hash(db: &DB, hash: &str) -> Result<String, ServerDBError> {
+    let working_dir_cf = db.kv.cf_handle(CF_WORKING_DIR).ok_or_else(|| {
+        ServerDBError::Fundamental("Missing working_dir column family".to_owned())
+    })?;
 
-fn get_blocks_by_hash(rocks_db: &rocksdb::DB, hash: &str) -> Result<String, ServerDBError> {
-    rocks_db
-        .get(working_dir_key(hash))
+    db.kv
+        .get_cf(working_dir_cf, hash)
         .map_err(|e| ServerDBError::Error(format!("Cannot read working dir key: {:?}", e)))?
         .map(|bs| String::from_utf8(bs).unwrap())
         .ok_or(ServerDBError::Consistency(
@@ -138,13 +296,13 @@ pub fn read_descendants_of_commit(db: &DB, hash: &str) -> Result<Vec<Commit>, Se
         .relational
         .prepare(
             "
-            WITH RECURSIVE ancestor_commits(hash, prev_commit_hash, project_id, branch, message, author, date, header) AS (
-                SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header FROM commits WHERE hash = ?1
+            WITH RECURSIVE ancestor_commits(hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature) AS (
+                SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature FROM commits WHERE hash = ?1
                 UNION ALL
-                SELECT c.hash, c.prev_commit_hash, c.project_id, c.branch, c.message, c.author, c.date, c.header FROM commits c
+                SELECT c.hash, c.prev_commit_hash, c.project_id, c.branch, c.message, c.author, c.date, c.header, c.author_pubkey, c.signature FROM commits c
                 JOIN ancestor_commits a ON c.prev_commit_hash = a.hash
             )
-            SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header FROM ancestor_commits ORDER BY date ASC;
+            SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature FROM ancestor_commits ORDER BY date ASC;
             ",
         )
         .map_err(|e| {
@@ -163,7 +321,7 @@ pub fn read_descendants_of_commit(db: &DB, hash: &str) -> Result<Vec<Commit>, Se
             .expect("No hash found in row")
             .to_string();
 
-        let blocks = get_blocks_by_hash(&db.kv, &hash)?;
+        let blocks = get_blocks_by_hash(db, &hash)?;
 
         result.push(Commit {
             hash,
@@ -174,8 +332,11 @@ pub fn read_descendants_of_commit(db: &DB, hash: &str) -> Result<Vec<Commit>, Se
             author: data.get(5).expect("No author found in row"),
             date: data.get(6).expect("No date found in row"),
             header: data.get(7).expect("No header found in row"),
+            author_pubkey: data.get(8).expect("No author_pubkey found in row"),
+            signature: data.get(9).expect("No signature found in row"),
             blocks,
-        })
+        });
+        COMMITS_READ.inc();
     }
 
     Ok(result)
@@ -204,7 +365,7 @@ pub fn read_commits_with_project_id(
             .expect("No hash found in row")
             .to_string();
 
-        let blocks = get_blocks_by_hash(&db.kv, &hash)?;
+        let blocks = get_blocks_by_hash(db, &hash)?;
 
         result.push(Commit {
             hash,
@@ -215,8 +376,92 @@ pub fn read_commits_with_project_id(
             author: data.get(5).expect("No author found in row"),
             date: data.get(6).expect("No date found in row"),
             header: data.get(7).expect("No header found in row"),
+            author_pubkey: data.get(8).expect("No author_pubkey found in row"),
+            signature: data.get(9).expect("No signature found in row"),
             blocks,
-        })
+        });
+        COMMITS_READ.inc();
+    }
+
+    Ok(result)
+}
+
+fn short_commit_from_row(data: &rusqlite::Row) -> ShortCommit {
+    ShortCommit {
+        hash: data.get(0).expect("No hash found in row"),
+        prev_commit_hash: data.get(1).expect("No prev_commit_hash found in row"),
+        project_id: data.get(2).expect("No project_id found in row"),
+        branch: data.get(3).expect("No branch found in row"),
+        message: data.get(4).expect("No message found in row"),
+        author: data.get(5).expect("No author found in row"),
+        date: data.get(6).expect("No date found in row"),
+        header: data.get(7).expect("No header found in row"),
+        author_pubkey: data.get(8).expect("No author_pubkey found in row"),
+        signature: data.get(9).expect("No signature found in row"),
+    }
+}
+
+/// Header-only counterpart to [`read_descendants_of_commit`]: same ancestor walk,
+/// but never touches `kv`, so a caller that only needs metadata isn't paying for one
+/// RocksDB point lookup per commit on top of the SQLite scan.
+pub fn read_descendant_headers(db: &DB, hash: &str) -> Result<Vec<ShortCommit>, ServerDBError> {
+    let mut stmt = db
+        .relational
+        .prepare(
+            "
+            WITH RECURSIVE ancestor_commits(hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature) AS (
+                SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature FROM commits WHERE hash = ?1
+                UNION ALL
+                SELECT c.hash, c.prev_commit_hash, c.project_id, c.branch, c.message, c.author, c.date, c.header, c.author_pubkey, c.signature FROM commits c
+                JOIN ancestor_commits a ON c.prev_commit_hash = a.hash
+            )
+            SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature FROM ancestor_commits ORDER BY date ASC;
+            ",
+        )
+        .map_err(|e| {
+            ServerDBError::Fundamental(format!("Cannot prepare read commits query: {:?}", e))
+        })?;
+
+    let mut rows = stmt
+        .query([hash])
+        .map_err(|e| ServerDBError::Error(format!("Cannot read commits: {:?}", e)))?;
+
+    let mut result: Vec<ShortCommit> = vec![];
+
+    while let Ok(Some(data)) = rows.next() {
+        result.push(short_commit_from_row(data));
+        COMMITS_READ.inc();
+    }
+
+    Ok(result)
+}
+
+/// Header-only counterpart to [`read_commits_with_project_id`]: same single SQLite
+/// scan, but never touches `kv`, so listing a project's history doesn't cost one
+/// RocksDB point lookup per commit for callers (branch listings, graph rendering,
+/// existence checks) that never look at `blocks`.
+pub fn read_commit_headers_of_project(
+    db: &DB,
+    project_id: &str,
+) -> Result<Vec<ShortCommit>, ServerDBError> {
+    let mut stmt = db
+        .relational
+        .prepare(
+            "SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, author_pubkey, signature FROM commits WHERE project_id = ?1;",
+        )
+        .map_err(|e| {
+            ServerDBError::Fundamental(format!("Cannot prepare read commits query: {:?}", e))
+        })?;
+
+    let mut rows = stmt
+        .query([project_id])
+        .map_err(|e| ServerDBError::Error(format!("Cannot read commits: {:?}", e)))?;
+
+    let mut result: Vec<ShortCommit> = vec![];
+
+    while let Ok(Some(data)) = rows.next() {
+        result.push(short_commit_from_row(data));
+        COMMITS_READ.inc();
     }
 
     Ok(result)
@@ -5,13 +5,18 @@ use redis::Commands;
 use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::utils::{gen_code, e500};
-
-
+use crate::{
+    ratelimit::RATE_LIMIT_CONFIG,
+    utils::{e500, gen_code},
+};
 
 pub const REDIS_URL: &str = "redis://127.0.0.1/";
 const EXPIRATION_SECS: usize = 300;
 
+fn attempt_count_key(activation_id: &str) -> String {
+    format!("login_attempts:{}", activation_id)
+}
+
 #[derive(Deserialize)]
 pub struct LoginInitInfo {
     email: String,
@@ -62,6 +67,21 @@ pub async fn login_complete(
     let code = value.unwrap();
 
     if code != data.code {
+        let attempts_key = attempt_count_key(&data.activation_id);
+        let attempts: Result<usize, redis::RedisError> = conn
+            .incr(&attempts_key, 1)
+            .and_then(|n: usize| conn.expire(&attempts_key, EXPIRATION_SECS).map(|_: ()| n));
+
+        if attempts.unwrap_or(0) >= RATE_LIMIT_CONFIG.max_code_attempts {
+            // Too many wrong codes against this activation_id -- kill it outright so
+            // the 6-digit code can't keep being brute-forced, forcing a fresh
+            // login_init with a new code.
+            let _: Result<(), redis::RedisError> = conn.del(&data.activation_id);
+            let _: Result<(), redis::RedisError> = conn.del(&attempts_key);
+            return HttpResponse::Unauthorized()
+                .json("too many incorrect attempts, start a new login");
+        }
+
         return HttpResponse::Unauthorized().json("incorrect code");
     }
 
@@ -69,6 +89,7 @@ pub async fn login_complete(
     if delete_result.is_err() {
         return HttpResponse::InternalServerError().json("Internal error");
     }
+    let _: Result<(), redis::RedisError> = conn.del(&attempt_count_key(&data.activation_id));
 
     session.insert(EMAIL_KEY, &data.email).unwrap();
 
@@ -1,18 +1,26 @@
+mod block_store;
+mod capability;
 mod clone;
 mod db;
 mod login;
+mod metrics;
+mod negotiate;
+mod ratelimit;
 mod sync;
 mod utils;
 
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
     cookie::Key,
-    web::{get, post, PayloadConfig},
+    web::{get, post, scope, PayloadConfig},
     App, HttpServer,
 };
 use clone::clone_project;
 use login::{authenticated_endpoint, login_complete, login_init, REDIS_URL};
-use sync::v1_sync;
+use metrics::metrics_endpoint;
+use negotiate::{diff_blocks_via_bloom, negotiate_clone, negotiate_push};
+use ratelimit::RateLimit;
+use sync::{fetch_blocks, v1_sync};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -25,11 +33,23 @@ async fn main() -> std::io::Result<()> {
                 store.to_owned(),
                 secret_key.to_owned(),
             ))
-            .route("/login/init", post().to(login_init))
-            .route("/login/complete", post().to(login_complete))
+            .service(
+                scope("/login")
+                    .wrap(RateLimit)
+                    .route("/init", post().to(login_init))
+                    .route("/complete", post().to(login_complete)),
+            )
             .route("/check", get().to(authenticated_endpoint))
             .route("/v1/sync", post().to(v1_sync))
+            .route("/v1/sync/negotiate", post().to(negotiate_push))
+            .route("/v1/fetch-blocks", post().to(fetch_blocks))
             .route("/v1/clone/{project_id}", get().to(clone_project))
+            .route("/v1/clone/{project_id}/negotiate", post().to(negotiate_clone))
+            .route(
+                "/v1/clone/{project_id}/diff-bloom",
+                post().to(diff_blocks_via_bloom),
+            )
+            .route("/metrics", get().to(metrics_endpoint))
             .app_data(PayloadConfig::new(1000000 * 250))
     })
     .bind("127.0.0.1:13337")?
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use timeline_lib::{api::common::parse_hash_list, exchange::bloom_filter::BloomFilter};
+
+use crate::{
+    capability::{authorize, Scope},
+    db::{missing_block_hashes, open_db, read_commits_with_project_id},
+    metrics::{BLOCKS_DEDUPLICATED, ENDPOINT_LATENCY_SECONDS, ENDPOINT_REQUESTS},
+    sync::bloom_filter_from_request,
+    utils::e500,
+};
+
+#[derive(Serialize)]
+pub struct NegotiateResponse {
+    missing_hashes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NegotiatePushRequest {
+    project_id: String,
+    block_hashes: Vec<String>,
+}
+
+/// Pre-flight for `v1_sync`: a client about to push new commits sends every block
+/// hash those commits reference (derivable client-side from `BlockMetadata` via
+/// `parse_blocks_and_pointers`), and gets back only the ones the server doesn't
+/// already have. The `v1_sync` call that follows can then fill `Exchange::blocks`
+/// with just this subset instead of the client's full `block_data`, which today gets
+/// uploaded regardless of how much of it the server already holds.
+pub async fn negotiate_push(
+    req: HttpRequest,
+    data: web::Json<NegotiatePushRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ENDPOINT_REQUESTS
+        .with_label_values(&["negotiate_push"])
+        .inc();
+    let timer = ENDPOINT_LATENCY_SECONDS
+        .with_label_values(&["negotiate_push"])
+        .start_timer();
+
+    let NegotiatePushRequest {
+        project_id,
+        block_hashes,
+    } = data.into_inner();
+
+    if let Err(response) = authorize(&req, &project_id, Scope::Append) {
+        return Ok(response);
+    }
+
+    let sent = block_hashes.len();
+    let missing_hashes = web::block(move || -> Result<Vec<String>, String> {
+        let db = open_db("/Users/bertalankormendy/Developer/timeline-backend/timeline-backend")
+            .map_err(|e| format!("{:?}", e))?;
+        missing_block_hashes(&db, block_hashes).map_err(|e| format!("{:?}", e))
+    })
+    .await
+    .map_err(e500)?
+    .map_err(e500)?;
+
+    BLOCKS_DEDUPLICATED.inc_by((sent - missing_hashes.len()) as u64);
+
+    timer.observe_duration();
+    Ok(HttpResponse::Ok().json(NegotiateResponse { missing_hashes }))
+}
+
+#[derive(Deserialize)]
+pub struct NegotiateCloneRequest {
+    have_hashes: Vec<String>,
+}
+
+/// Inverse of `negotiate_push`, for `clone_project`: a client with a partial local
+/// history (say, from an interrupted clone) sends the block hashes it already has,
+/// and gets back only the ones the project's commits reference that it's still
+/// missing -- so a follow-up `fetch_blocks` call can ask for exactly that subset
+/// instead of re-downloading blocks the client already holds.
+pub async fn negotiate_clone(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    data: web::Json<NegotiateCloneRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ENDPOINT_REQUESTS
+        .with_label_values(&["negotiate_clone"])
+        .inc();
+    let timer = ENDPOINT_LATENCY_SECONDS
+        .with_label_values(&["negotiate_clone"])
+        .start_timer();
+
+    let (project_id,) = path.into_inner();
+
+    if let Err(response) = authorize(&req, &project_id, Scope::Read) {
+        return Ok(response);
+    }
+
+    let have_hashes: HashSet<String> = data.into_inner().have_hashes.into_iter().collect();
+    let missing_hashes = web::block(move || -> Result<Vec<String>, String> {
+        let db = open_db("/Users/bertalankormendy/Developer/timeline-backend/timeline-backend")
+            .map_err(|e| format!("{:?}", e))?;
+
+        let mut referenced_hashes: HashSet<String> = HashSet::new();
+        for commit in
+            read_commits_with_project_id(&db, &project_id).map_err(|e| format!("{:?}", e))?
+        {
+            referenced_hashes.extend(parse_hash_list(commit.blocks));
+        }
+
+        Ok(referenced_hashes
+            .into_iter()
+            .filter(|hash| !have_hashes.contains(hash))
+            .collect())
+    })
+    .await
+    .map_err(e500)?
+    .map_err(e500)?;
+
+    timer.observe_duration();
+    Ok(HttpResponse::Ok().json(NegotiateResponse { missing_hashes }))
+}
+
+/// Bloom-filter counterpart to [`negotiate_clone`]: instead of the client enumerating
+/// every block hash it already has in the request body, it sends a [`BloomFilter`]
+/// summarizing that set in the `X-Bloom-Filter` header (see [`crate::sync`], which
+/// already does this for push/pull via `prepare_exchange_response`/`fetch_blocks`).
+/// A hash the filter claims to contain is skipped, so a false positive leaves out a
+/// block the client doesn't actually have; the client finds that block missing once
+/// it applies the commits and asks for it by exact hash via `fetch_blocks`. This is a
+/// cheaper-on-the-wire alternative to `negotiate_clone` for a client whose local
+/// history is too large to list hash-by-hash.
+pub async fn diff_blocks_via_bloom(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ENDPOINT_REQUESTS
+        .with_label_values(&["diff_blocks_via_bloom"])
+        .inc();
+    let timer = ENDPOINT_LATENCY_SECONDS
+        .with_label_values(&["diff_blocks_via_bloom"])
+        .start_timer();
+
+    let (project_id,) = path.into_inner();
+
+    if let Err(response) = authorize(&req, &project_id, Scope::Read) {
+        return Ok(response);
+    }
+
+    let peer_has: Option<BloomFilter> = bloom_filter_from_request(&req);
+    let missing_hashes = web::block(move || -> Result<Vec<String>, String> {
+        let db = open_db("/Users/bertalankormendy/Developer/timeline-backend/timeline-backend")
+            .map_err(|e| format!("{:?}", e))?;
+
+        let mut referenced_hashes: HashSet<String> = HashSet::new();
+        for commit in
+            read_commits_with_project_id(&db, &project_id).map_err(|e| format!("{:?}", e))?
+        {
+            referenced_hashes.extend(parse_hash_list(commit.blocks));
+        }
+
+        Ok(referenced_hashes
+            .into_iter()
+            .filter(|hash| !peer_has.as_ref().is_some_and(|f| f.might_contain(hash)))
+            .collect())
+    })
+    .await
+    .map_err(e500)?
+    .map_err(e500)?;
+
+    timer.observe_duration();
+    Ok(HttpResponse::Ok().json(NegotiateResponse { missing_hashes }))
+}
@@ -0,0 +1,162 @@
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use once_cell::sync::Lazy;
+use redis::Commands;
+use uuid::Uuid;
+
+use crate::login::REDIS_URL;
+
+/// Sliding-window size, burst per window, and failed-code-attempt cap -- all
+/// overridable via env so an operator can retune them without a redeploy.
+pub struct RateLimitConfig {
+    pub window_secs: usize,
+    pub burst: usize,
+    pub max_code_attempts: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            window_secs: 60,
+            burst: 10,
+            max_code_attempts: 5,
+        }
+    }
+}
+
+pub static RATE_LIMIT_CONFIG: Lazy<RateLimitConfig> = Lazy::new(|| {
+    let default = RateLimitConfig::default();
+    RateLimitConfig {
+        window_secs: std::env::var("RATE_LIMIT_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.window_secs),
+        burst: std::env::var("RATE_LIMIT_BURST")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.burst),
+        max_code_attempts: std::env::var("RATE_LIMIT_MAX_CODE_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.max_code_attempts),
+    }
+});
+
+/// Checks `key` against a Redis-backed sliding-window log: each call's timestamp is a
+/// member of a sorted set, entries older than `window_secs` fall out of the window on
+/// every check, and the call counts toward the limit only if it's admitted. Returns
+/// `Err(retry_after_secs)` once `limit` has been reached within the window.
+fn check_sliding_window(
+    conn: &mut redis::Connection,
+    key: &str,
+    limit: usize,
+    window_secs: usize,
+) -> Result<(), u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let window_start = now.saturating_sub(window_secs as u64);
+
+    let _: Result<(), redis::RedisError> =
+        conn.zrembyscore(key, 0, window_start as isize);
+    let count: usize = conn.zcard(key).unwrap_or(0);
+
+    if count >= limit {
+        return Err(window_secs as u64);
+    }
+
+    // Unique member per call so calls landing in the same second each count.
+    let member = format!("{}-{}", now, Uuid::new_v4());
+    let _: Result<(), redis::RedisError> = conn.zadd(key, member, now as f64);
+    let _: Result<(), redis::RedisError> = conn.expire(key, window_secs);
+
+    Ok(())
+}
+
+/// Per-IP rate limiter for the login endpoints, keyed by `req.connection_info()`'s
+/// resolved remote address. Attempt-tracking keyed by `activation_id`/`email` lives in
+/// `login.rs` instead: that needs the parsed request body, which isn't available at
+/// the middleware layer.
+pub struct RateLimit;
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let client_ip = req
+            .connection_info()
+            .realip_remote_addr()
+            .unwrap_or("unknown")
+            .to_string();
+
+        Box::pin(async move {
+            let client = redis::Client::open(REDIS_URL)
+                .map_err(|e| actix_web::error::ErrorInternalServerError(format!("{:?}", e)))?;
+            let mut conn = client
+                .get_connection()
+                .map_err(|e| actix_web::error::ErrorInternalServerError(format!("{:?}", e)))?;
+
+            let key = format!("ratelimit:ip:{}", client_ip);
+            if let Err(retry_after) = check_sliding_window(
+                &mut conn,
+                &key,
+                RATE_LIMIT_CONFIG.burst,
+                RATE_LIMIT_CONFIG.window_secs,
+            ) {
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header((
+                        HeaderName::from_static("retry-after"),
+                        HeaderValue::from_str(&retry_after.to_string()).unwrap(),
+                    ))
+                    .json("rate limit exceeded, try again later");
+                return Ok(req.into_response(response));
+            }
+
+            service.call(req).await
+        })
+    }
+}
@@ -30,6 +30,22 @@ fn error_if_not_exists(db_path: &str) -> Result<(), DBError> {
     Ok(())
 }
 
+/// Runs a blocking `timeline_lib` call on actix-web's blocking threadpool instead of
+/// the async reactor, so a slow DB transaction or blend-file parse on one request
+/// doesn't stall every other connection. Collapses `web::block`'s own
+/// `BlockingError` (the closure panicked) into a 500 -- the DB/consistency errors the
+/// closure itself returns still flow through unchanged as `Ok(Err(DBError))`.
+async fn run_blocking<T, F>(f: F) -> Result<T, HttpResponse>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(f).await.map_err(|err| {
+        error!("{}", err);
+        HttpResponse::InternalServerError().json("blocking task panicked")
+    })
+}
+
 #[get("/healthcheck")]
 pub async fn healthcheck() -> impl Responder {
     HttpResponse::Ok().json("Running")
@@ -44,11 +60,18 @@ pub struct CommitPayload {
 
 #[post("/commit")]
 pub async fn commit(data: Json<CommitPayload>) -> impl Responder {
-    let result = create_new_commit(
-        &data.file_path,
-        &data.db_path,
-        Some(data.message.to_owned()),
-    );
+    let result = match run_blocking(move || {
+        create_new_commit(
+            &data.file_path,
+            &data.db_path,
+            Some(data.message.to_owned()),
+        )
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
 
     match result {
         Err(err) => {
@@ -69,8 +92,14 @@ struct ShortCommitPayload {
 pub async fn checkpoints(path: web::Path<(String, String)>) -> impl Responder {
     let (db_path, branch_name) = path.into_inner();
 
-    let result =
-        error_if_not_exists(&db_path).and_then(|_| list_checkpoints(&db_path, &branch_name));
+    let result = match run_blocking(move || {
+        error_if_not_exists(&db_path).and_then(|_| list_checkpoints(&db_path, &branch_name))
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
 
     match result {
         Ok(checkpoints) => HttpResponse::Ok().json(
@@ -98,7 +127,14 @@ pub struct RestorePayload {
 
 #[post("/restore")]
 pub async fn restore(data: Json<RestorePayload>) -> impl Responder {
-    let result = restore_checkpoint(&data.file_path, &data.db_path, &data.hash);
+    let result = match run_blocking(move || {
+        restore_checkpoint(&data.file_path, &data.db_path, &data.hash, false)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
 
     match result {
         Ok(_) => HttpResponse::Ok().json("OK"),
@@ -112,7 +148,14 @@ pub async fn restore(data: Json<RestorePayload>) -> impl Responder {
 #[get("/branches/{db_path}")]
 pub async fn branches(path: web::Path<(String,)>) -> impl Responder {
     let (db_path,) = path.into_inner();
-    let result = error_if_not_exists(&db_path).and_then(|_| list_braches(&db_path));
+    let result = match run_blocking(move || {
+        error_if_not_exists(&db_path).and_then(|_| list_braches(&db_path))
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
     match result {
         Ok(branches) => HttpResponse::Ok().json(branches),
         Err(err) => {
@@ -130,7 +173,11 @@ pub struct NewBranchPayload {
 
 #[post("/branches/new")]
 pub async fn new_branch(data: Json<NewBranchPayload>) -> impl Responder {
-    let result = create_new_branch(&data.db_path, &data.branch_name);
+    let result = match run_blocking(move || create_new_branch(&data.db_path, &data.branch_name)).await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
     match result {
         Ok(_) => HttpResponse::Ok().json("OK"),
         Err(err) => {
@@ -149,7 +196,14 @@ pub struct SwitchBranchPayload {
 
 #[post("/branches/switch")]
 pub async fn switch_branch(data: Json<SwitchBranchPayload>) -> impl Responder {
-    let result = switch_branches(&data.db_path, &data.branch_name, &data.file_path);
+    let result = match run_blocking(move || {
+        switch_branches(&data.db_path, &data.branch_name, &data.file_path)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
     match result {
         Ok(_) => HttpResponse::Ok().json("OK"),
         Err(err) => {
@@ -162,8 +216,14 @@ pub async fn switch_branch(data: Json<SwitchBranchPayload>) -> impl Responder {
 #[get("/branches/current/{db_path}")]
 pub async fn read_current_branch(path: web::Path<(String,)>) -> impl Responder {
     let (db_path,) = path.into_inner();
-    let result = error_if_not_exists(&db_path)
-        .and_then(|_| get_current_branch::get_current_branch(&db_path));
+    let result = match run_blocking(move || {
+        error_if_not_exists(&db_path).and_then(|_| get_current_branch::get_current_branch(&db_path))
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
     match result {
         Ok(branch) => HttpResponse::Ok().json(branch),
         Err(err) => {
@@ -176,8 +236,14 @@ pub async fn read_current_branch(path: web::Path<(String,)>) -> impl Responder {
 #[get("/commit/current/{db_path}")]
 pub async fn read_current_commit_hash(path: web::Path<(String,)>) -> impl Responder {
     let (db_path,) = path.into_inner();
-    let result = error_if_not_exists(&db_path)
-        .and_then(|_| get_current_commit::get_current_commit(&db_path));
+    let result = match run_blocking(move || {
+        error_if_not_exists(&db_path).and_then(|_| get_current_commit::get_current_commit(&db_path))
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
     match result {
         Ok(branch) => HttpResponse::Ok().json(branch),
         Err(err) => {
@@ -201,7 +267,13 @@ pub async fn connect(data: Json<ConnectDBPayload>) -> impl Responder {
     }
 
     let project_id = uuid::Uuid::new_v4().to_string();
-    let result = init_command::init_db(&data.db_path, &project_id, &data.file_path);
+    let result =
+        match run_blocking(move || init_command::init_db(&data.db_path, &project_id, &data.file_path))
+            .await
+        {
+            Ok(result) => result,
+            Err(response) => return response,
+        };
     match result {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(err) => {
@@ -68,6 +68,10 @@ pub fn create_new_commit(
             date: file_last_mod_time as u64,
             header: blend_data.header_bytes,
             blocks: blend_data.blocks,
+            // Unsigned by default: this crate doesn't manage author keypairs yet, so
+            // `signing::sign_commit` is opt-in for callers that do.
+            author_pubkey: vec![],
+            signature: vec![],
         };
         Persistence::write_commit(tx, commit)
     })?;
@@ -2,6 +2,7 @@ use filetime::FileTime;
 use flate2::{write::GzEncoder, Compression};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
 use crate::{
     blend::{
@@ -65,6 +66,89 @@ pub struct BlockMetadata {
     pub hash: String,
     pub original_mem_address: Either<u32, u64>,
     pub pointers: OffsetsWithPointerValue, // offset with pointer value
+    /// Ordered content-defined sub-chunk hashes that reassemble into the block's
+    /// uncompressed bytes. Each hash is also the key its compressed chunk is stored
+    /// under, so identical sub-chunks across blocks/commits are shared even when an
+    /// edit only touches part of a large block.
+    pub chunk_hashes: Vec<String>,
+}
+
+// Gear/rolling-hash content-defined chunking, tuned for the block sizes blend files
+// tend to produce (small DNA/struct blocks up to multi-megabyte mesh/image blocks).
+const CDC_MIN_SIZE: usize = 2 * 1024;
+const CDC_AVG_SIZE: usize = 8 * 1024;
+const CDC_MAX_SIZE: usize = 64 * 1024;
+
+// Normalized chunking: a stricter (more one-bits) mask makes cuts rarer before the
+// average size, a looser mask makes them more likely after it, pulling the size
+// distribution tight around `CDC_AVG_SIZE` instead of the wide spread a single mask
+// produces.
+const CDC_MASK_S: u64 = (1u64 << 15) - 1;
+const CDC_MASK_L: u64 = (1u64 << 11) - 1;
+
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        // xorshift64 seeded with a fixed constant: deterministic across runs/machines,
+        // which matters because the table doubles as part of the chunking algorithm.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut table = [0u64; 256];
+        for slot in table.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *slot = state;
+        }
+        table
+    })
+}
+
+/// Splits `data` into content-defined chunks so that edits to a small region of a
+/// large block only change the chunks touching that region, instead of the whole
+/// block's hash.
+pub fn chunk_content_defined(data: &[u8]) -> Vec<&[u8]> {
+    if data.len() <= CDC_MIN_SIZE {
+        return vec![data];
+    }
+
+    let gear = gear_table();
+    let mut chunks = vec![];
+    let mut start = 0usize;
+
+    while start < data.len() {
+        let remaining = data.len() - start;
+        if remaining <= CDC_MIN_SIZE {
+            chunks.push(&data[start..]);
+            break;
+        }
+
+        let mut fp: u64 = 0;
+        let mut offset = CDC_MIN_SIZE;
+        let mut cut = None;
+
+        while offset < CDC_MAX_SIZE && start + offset < data.len() {
+            let byte = data[start + offset];
+            fp = (fp << 1).wrapping_add(gear[byte as usize]);
+
+            let mask = if offset < CDC_AVG_SIZE {
+                CDC_MASK_S
+            } else {
+                CDC_MASK_L
+            };
+
+            if fp & mask == 0 {
+                cut = Some(offset);
+                break;
+            }
+            offset += 1;
+        }
+
+        let chunk_len = cut.unwrap_or_else(|| std::cmp::min(CDC_MAX_SIZE, remaining));
+        chunks.push(&data[start..start + chunk_len]);
+        start += chunk_len;
+    }
+
+    chunks
 }
 
 pub fn blend_file_data_from_file(
@@ -82,8 +166,13 @@ pub fn blend_file_data_from_file(
 
     println!("Number of blocks: {:?}", parsed_blend.blocks.len());
 
-    let block_data_with_meta: Vec<(BlockMetadata, Vec<u8>)> =
-        measure_time!(format!("Hashing blocks {:?}", path_to_blend), {
+    struct ChunkedBlock {
+        meta: BlockMetadata,
+        chunks: Vec<(String, Vec<u8>)>,
+    }
+
+    let chunked_blocks: Vec<ChunkedBlock> =
+        measure_time!(format!("Chunking and hashing blocks {:?}", path_to_blend), {
             parsed_blend
                 .blocks
                 .into_par_iter()
@@ -91,45 +180,61 @@ pub fn blend_file_data_from_file(
                     let mut block_blob: Vec<u8> = vec![];
                     print_block_manual(parsed_block.simple_block, endianness, &mut block_blob);
 
-                    let hash = md5::compute(&block_blob);
+                    let hash = format!("{:x}", md5::compute(&block_blob));
 
-                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-                    encoder
-                        .write_all(&block_blob)
-                        .map_err(|e| format!("Cannot encode: {:?}", e))?;
-                    let compressed = encoder
-                        .finish()
-                        .map_err(|e| format!("Cannot encode: {:?}", e))?;
-
-                    Ok((
-                        BlockMetadata {
-                            hash: format!("{:x}", hash),
+                    let chunks: Vec<(String, Vec<u8>)> = chunk_content_defined(&block_blob)
+                        .into_iter()
+                        .map(|chunk| (format!("{:x}", md5::compute(chunk)), chunk.to_vec()))
+                        .collect();
+                    let chunk_hashes = chunks.iter().map(|(hash, _)| hash.clone()).collect();
+
+                    ChunkedBlock {
+                        meta: BlockMetadata {
+                            hash,
                             original_mem_address: parsed_block.original_mem_address,
                             pointers: parsed_block.pointers,
+                            chunk_hashes,
                         },
-                        compressed,
-                    ))
+                        chunks,
+                    }
                 })
-                .collect::<Vec<Result<(BlockMetadata, Vec<u8>), String>>>()
-                .into_iter()
-                .collect::<Result<Vec<(BlockMetadata, Vec<u8>)>, String>>()
-        })?;
+                .collect()
+        });
+
+    // Many blocks share identical sub-chunks (e.g. structurally similar small
+    // blocks), so de-dup by hash before compressing.
+    let mut unique_chunks: std::collections::HashMap<String, Vec<u8>> =
+        std::collections::HashMap::new();
+    for block in &chunked_blocks {
+        for (chunk_hash, chunk_bytes) in &block.chunks {
+            unique_chunks
+                .entry(chunk_hash.clone())
+                .or_insert_with(|| chunk_bytes.clone());
+        }
+    }
 
     let mut header_data: Vec<u8> = vec![];
     print_header_manual(parsed_blend.header, &mut header_data);
 
-    let block_records: Vec<BlockRecord> = block_data_with_meta
-        .par_iter()
-        .map(|(meta, data)| BlockRecord {
-            hash: meta.hash.clone(),
-            data: data.to_owned(),
-        })
-        .collect();
-
-    let blocks_meta: Vec<BlockMetadata> = block_data_with_meta
-        .into_iter()
-        .map(|(meta, _)| meta)
-        .collect();
+    let block_records: Vec<BlockRecord> = measure_time!(
+        format!("Compressing blocks {:?}", path_to_blend),
+        {
+            unique_chunks
+                .into_par_iter()
+                .map(|(hash, bytes)| {
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&bytes).expect("Cannot encode chunk");
+                    let compressed = encoder.finish().expect("Cannot encode chunk");
+                    BlockRecord {
+                        hash,
+                        data: compressed,
+                    }
+                })
+                .collect()
+        }
+    );
+
+    let blocks_meta: Vec<BlockMetadata> = chunked_blocks.into_iter().map(|block| block.meta).collect();
 
     let block_meta_bytes = print_blocks_and_pointers(blocks_meta);
 
@@ -37,6 +37,8 @@ pub fn init_db(db_path: &str, project_id: &str, path_to_blend: &str) -> Result<(
             date: file_last_mod_time as u64,
             header: blend_data.header_bytes,
             blocks: blend_data.blocks,
+            author_pubkey: vec![],
+            signature: vec![],
         };
 
         Persistence::write_commit(tx, commit)
@@ -4,13 +4,18 @@ use flate2::write::GzDecoder;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    api::common::parse_hash_list,
+    api::{common::parse_hash_list, signing::verify_commit},
     blend::utils::to_file_transactional,
     db::db_ops::{DBError, Persistence, DB},
     measure_time,
 };
 
-pub fn restore_checkpoint(file_path: &str, db_path: &str, hash: &str) -> Result<(), DBError> {
+pub fn restore_checkpoint(
+    file_path: &str,
+    db_path: &str,
+    hash: &str,
+    require_signatures: bool,
+) -> Result<(), DBError> {
     let end_to_end_timer = Instant::now();
 
     let mut conn = Persistence::open(db_path)?;
@@ -20,6 +25,8 @@ pub fn restore_checkpoint(file_path: &str, db_path: &str, hash: &str) -> Result<
             .ok_or(DBError::Consistency("no such commit found".to_owned()))
     })?;
 
+    verify_commit(&commit, require_signatures)?;
+
     let block_hashes = measure_time!(format!("Reading blocks {:?}", hash), {
         parse_hash_list(commit.blocks)
     });
@@ -83,6 +90,7 @@ mod test {
             tmp_blend_path.path().to_str().unwrap(),
             tmp_path,
             "b637ec695e10bed0ce06279d1dc46717",
+            false,
         )
         .expect("Cannot restore checkpoint");
 
@@ -0,0 +1,154 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::db::{db_ops::DBError, structs::Commit};
+
+/// Canonical, order-fixed byte representation of the fields that define a commit's
+/// content and place in history -- everything except the content hash itself and the
+/// signing fields, which obviously can't be inputs to their own signature.
+fn canonical_commit_bytes(commit: &Commit) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(commit.prev_commit_hash.as_bytes());
+    bytes.extend_from_slice(commit.project_id.as_bytes());
+    bytes.extend_from_slice(commit.branch.as_bytes());
+    bytes.extend_from_slice(commit.message.as_bytes());
+    bytes.extend_from_slice(commit.author.as_bytes());
+    bytes.extend_from_slice(&commit.date.to_be_bytes());
+    bytes.extend_from_slice(&commit.header);
+    bytes.extend_from_slice(commit.blocks.as_bytes());
+    bytes
+}
+
+/// Hash signed/verified in place of the raw commit bytes, since ed25519 signs short
+/// fixed-size messages best.
+pub fn canonical_commit_hash(commit: &Commit) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(canonical_commit_bytes(commit));
+    hasher.finalize().into()
+}
+
+/// Signs `commit` with `signing_key`, filling in `author_pubkey`/`signature`. The
+/// fields being overwritten aren't part of the canonical bytes, so it doesn't matter
+/// what they held going in.
+pub fn sign_commit(mut commit: Commit, signing_key: &SigningKey) -> Commit {
+    let canonical_hash = canonical_commit_hash(&commit);
+    let signature: Signature = signing_key.sign(&canonical_hash);
+
+    commit.author_pubkey = signing_key.verifying_key().to_bytes().to_vec();
+    commit.signature = signature.to_bytes().to_vec();
+    commit
+}
+
+/// `Ok(())` when `commit` is unsigned (empty `signature`/`author_pubkey`) -- signing
+/// is opt-in, so unsigned commits from pre-signing history or peers must keep
+/// working. Pass `require_signatures: true` for the stricter trust policy that
+/// rejects unsigned commits outright.
+pub fn verify_commit(commit: &Commit, require_signatures: bool) -> Result<(), DBError> {
+    if commit.signature.is_empty() || commit.author_pubkey.is_empty() {
+        return if require_signatures {
+            Err(DBError::Consistency(format!(
+                "commit {} is unsigned but signatures are required",
+                commit.hash
+            )))
+        } else {
+            Ok(())
+        };
+    }
+
+    // `commit.hash` is the identifier everything else (branch tips, parent links,
+    // block lookups) trusts, but it isn't itself part of `canonical_commit_bytes` --
+    // so without this check a forged `hash` field would ride along on an otherwise
+    // valid signature over the real content.
+    let canonical_hash_hex = canonical_commit_hash(commit)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    if commit.hash != canonical_hash_hex {
+        return Err(DBError::Consistency(format!(
+            "commit {} has a hash that doesn't match its canonical content hash",
+            commit.hash
+        )));
+    }
+
+    let pubkey_bytes: [u8; 32] = commit.author_pubkey.clone().try_into().map_err(|_| {
+        DBError::Consistency(format!("commit {} has a malformed public key", commit.hash))
+    })?;
+    let verifying_key = VerifyingKey::from_bytes(&pubkey_bytes).map_err(|_| {
+        DBError::Consistency(format!("commit {} has an invalid public key", commit.hash))
+    })?;
+
+    let signature_bytes: [u8; 64] = commit.signature.clone().try_into().map_err(|_| {
+        DBError::Consistency(format!("commit {} has a malformed signature", commit.hash))
+    })?;
+    let signature = Signature::from_bytes(&signature_bytes);
+
+    verifying_key
+        .verify(&canonical_commit_hash(commit), &signature)
+        .map_err(|_| {
+            DBError::Consistency(format!("commit {} failed signature verification", commit.hash))
+        })
+}
+
+/// Verifies every commit in `commits`, stopping at (and reporting) the first failure
+/// -- used to reject a whole sync/restore exchange rather than applying it partially.
+pub fn verify_commits(commits: &[Commit], require_signatures: bool) -> Result<(), DBError> {
+    for commit in commits {
+        verify_commit(commit, require_signatures)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ed25519_dalek::SigningKey;
+
+    use super::{canonical_commit_hash, sign_commit, verify_commit};
+    use crate::db::structs::Commit;
+
+    fn draft_commit() -> Commit {
+        Commit {
+            hash: String::new(),
+            prev_commit_hash: "initial".to_owned(),
+            project_id: "my-cool-project".to_owned(),
+            branch: "main".to_owned(),
+            message: "Initial checkpoint".to_owned(),
+            author: "Anon".to_owned(),
+            date: 314,
+            header: vec![1, 2, 3],
+            blocks: "block-a,block-b".to_owned(),
+            author_pubkey: vec![],
+            signature: vec![],
+        }
+    }
+
+    fn signed_commit() -> Commit {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+
+        let mut commit = draft_commit();
+        commit.hash = canonical_commit_hash(&commit)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        sign_commit(commit, &signing_key)
+    }
+
+    #[test]
+    fn test_verify_commit_accepts_a_validly_signed_commit() {
+        let commit = signed_commit();
+        verify_commit(&commit, false).expect("validly signed commit should verify");
+    }
+
+    #[test]
+    fn test_verify_commit_rejects_a_forged_hash() {
+        let mut commit = signed_commit();
+        // Signature and canonical fields are untouched, only the identifier used for
+        // content addressing/branch tips/parent links is forged.
+        commit.hash = "forged-hash".to_owned();
+
+        assert!(
+            verify_commit(&commit, false).is_err(),
+            "a commit whose hash no longer matches its canonical content hash must be rejected"
+        );
+    }
+}
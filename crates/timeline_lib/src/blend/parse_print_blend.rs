@@ -1,5 +1,10 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{Read, Write},
+};
 
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use rayon::iter::{
     IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator,
 };
@@ -352,21 +357,81 @@ pub fn print_blend_manual(blend: ParsedBlendFile, out: &mut Vec<u8>) {
     out.extend(b"ENDB")
 }
 
-fn is_pointer(name: &str) -> bool {
-    name.starts_with('*')
+/// Compression wrapping the raw `BLENDER`-magic bytes on disk. Legacy files are
+/// commonly gzipped, and Blender 3.0+ writes zstd by default; detected once on
+/// parse and remembered so `print_blend` can write the file back the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+fn detect_compression(data: &[u8]) -> BlendCompression {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        BlendCompression::Gzip
+    } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        BlendCompression::Zstd
+    } else {
+        BlendCompression::None
+    }
+}
+
+fn decompress_blend(
+    data: Vec<u8>,
+    compression: BlendCompression,
+) -> Result<Vec<u8>, BlendFileParseError> {
+    match compression {
+        BlendCompression::None => Ok(data),
+        BlendCompression::Gzip => {
+            let mut decoder = GzDecoder::new(&data[..]);
+            let mut inflated = Vec::new();
+            decoder
+                .read_to_end(&mut inflated)
+                .map_err(|_| BlendFileParseError::ConversionFailed)?;
+            Ok(inflated)
+        }
+        BlendCompression::Zstd => {
+            zstd::decode_all(&data[..]).map_err(|_| BlendFileParseError::ConversionFailed)
+        }
+    }
+}
+
+fn compress_blend(data: Vec<u8>, compression: BlendCompression) -> Vec<u8> {
+    match compression {
+        BlendCompression::None => data,
+        BlendCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&data)
+                .expect("writing to an in-memory buffer cannot fail");
+            encoder
+                .finish()
+                .expect("finishing an in-memory gzip stream cannot fail")
+        }
+        BlendCompression::Zstd => {
+            zstd::encode_all(&data[..], 0).expect("in-memory zstd encoding cannot fail")
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 enum FieldType {
     Value,
     ValueArray { dimensions: Vec<usize> },
-    Pointer,
+    /// A pointer, or an array of pointers (DNA names like `*mat[4]`, `**obmat[2]`).
+    /// `dimensions` is empty for a plain, non-array pointer.
+    Pointer { dimensions: Vec<usize> },
     FnPointer,
 }
 
 fn parse_field_type(name: &str, re: &Regex) -> FieldType {
     if name.starts_with('*') || name.starts_with("**") {
-        return FieldType::Pointer;
+        let dimensions = re
+            .captures_iter(name)
+            .map(|c| c[1].parse::<usize>().unwrap())
+            .collect();
+        return FieldType::Pointer { dimensions };
     }
     if name.starts_with("(*") {
         return FieldType::FnPointer;
@@ -387,6 +452,47 @@ fn count_from_dimensions(dims: &[usize]) -> usize {
     dims.iter().product()
 }
 
+/// Indexed byte-level accessors for a parse source, so pointer extraction can read
+/// straight through a borrowed buffer instead of cloning the field's bytes into an
+/// owned `Vec` first. Implemented directly for `&[u8]`; a source tied to an mmap'd
+/// file buffer gets this for free. `SimpleParsedBlock::data` itself is still owned
+/// (its definition lives outside this module), so this covers the hot read path —
+/// field/pointer scanning — rather than the full block storage.
+pub trait BinarySource {
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&[u8], BlendFileParseError>;
+    fn u32_at(&self, offset: usize, endianness: Endianness) -> Result<u32, BlendFileParseError>;
+    fn u64_at(&self, offset: usize, endianness: Endianness) -> Result<u64, BlendFileParseError>;
+}
+
+impl BinarySource for [u8] {
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&[u8], BlendFileParseError> {
+        self.get(offset..offset + len)
+            .ok_or_else(|| BlendFileParseError::UnexpectedEndOfInput("bytes_at".to_string()))
+    }
+
+    fn u32_at(&self, offset: usize, endianness: Endianness) -> Result<u32, BlendFileParseError> {
+        let bytes: [u8; 4] = self
+            .bytes_at(offset, 4)?
+            .try_into()
+            .map_err(|_| BlendFileParseError::ConversionFailed)?;
+        Ok(match endianness {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u64_at(&self, offset: usize, endianness: Endianness) -> Result<u64, BlendFileParseError> {
+        let bytes: [u8; 8] = self
+            .bytes_at(offset, 8)?
+            .try_into()
+            .map_err(|_| BlendFileParseError::ConversionFailed)?;
+        Ok(match endianness {
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        })
+    }
+}
+
 pub type OffsetsWithPointerValue = Vec<(usize, Either<u32, u64>)>;
 
 pub struct BlockContentWithPointers {
@@ -428,32 +534,19 @@ pub fn parse_block_contents(
     };
 
     for &offset in fields {
-        let range_lo = offset;
-        let range_hi = std::cmp::min(offset + ptr_size, block.size as usize);
         if offset + ptr_size >= block.size as usize {
             continue;
         }
 
-        let data_for_field = &block.data[range_lo..range_hi].to_vec();
         match pointer_size {
             PointerSize::Bits32 => {
-                if let Ok(data) = std::convert::TryInto::<[u8; 4]>::try_into(data_for_field.clone())
-                {
-                    let from_fn = match endianness {
-                        Endianness::Little => u32::from_le_bytes,
-                        Endianness::Big => u32::from_be_bytes,
-                    };
-                    pointers.push((offset, Either::Left(from_fn(data))));
+                if let Ok(value) = block.data.as_slice().u32_at(offset, endianness) {
+                    pointers.push((offset, Either::Left(value)));
                 }
             }
             PointerSize::Bits64 => {
-                if let Ok(data) = std::convert::TryInto::<[u8; 8]>::try_into(data_for_field.clone())
-                {
-                    let from_fn = match endianness {
-                        Endianness::Little => u64::from_le_bytes,
-                        Endianness::Big => u64::from_be_bytes,
-                    };
-                    pointers.push((offset, Either::Right(from_fn(data))))
+                if let Ok(value) = block.data.as_slice().u64_at(offset, endianness) {
+                    pointers.push((offset, Either::Right(value)));
                 }
             }
         }
@@ -510,9 +603,12 @@ pub fn restore_block(
 pub struct BlendFileWithPointerData {
     pub header: Header,
     pub blocks: Vec<BlockContentWithPointers>,
+    pub compression: BlendCompression,
 }
 
 pub fn parse_blend(blend_data: Vec<u8>) -> Result<BlendFileWithPointerData, BlendFileParseError> {
+    let compression = detect_compression(&blend_data);
+    let blend_data = decompress_blend(blend_data, compression)?;
     let parsed_blend_file = parse_blend_manual(blend_data)?;
     let sdna = measure_time!("Finding SDNA block", {
         parsed_blend_file
@@ -554,10 +650,13 @@ pub fn parse_blend(blend_data: Vec<u8>) -> Result<BlendFileWithPointerData, Blen
     Ok(BlendFileWithPointerData {
         header: parsed_blend_file.header,
         blocks: blocks_with_pointer_data,
+        compression,
     })
 }
 
 pub fn print_blend(mut blend_file: BlendFileWithPointerData, out: &mut Vec<u8>) {
+    let compression = blend_file.compression;
+
     let restored_blocks: Vec<SimpleParsedBlock> = measure_time!("Restoring blocks", {
         blend_file
             .blocks
@@ -577,11 +676,145 @@ pub fn print_blend(mut blend_file: BlendFileWithPointerData, out: &mut Vec<u8>)
         blocks: restored_blocks,
     };
 
-    print_blend_manual(parsed_blend, out);
+    let mut raw = Vec::new();
+    print_blend_manual(parsed_blend, &mut raw);
+    out.extend(compress_blend(raw, compression));
 }
 
 pub type FieldMetaLookup = HashMap<u32, Vec<usize>>;
 
+/// A struct's computed C layout: its total size and alignment (needed to place it
+/// correctly as a member of an enclosing struct or array), plus every pointer slot
+/// it contains, as offsets relative to the start of the struct.
+#[derive(Debug, Clone)]
+struct StructLayout {
+    size: usize,
+    alignment: usize,
+    pointer_offsets: Vec<usize>,
+}
+
+fn align_up(offset: usize, alignment: usize) -> usize {
+    if alignment <= 1 {
+        return offset;
+    }
+    offset.div_ceil(alignment) * alignment
+}
+
+fn find_struct_index_by_type_idx(sdna_info: &DNAInfo, type_idx: i16) -> Option<usize> {
+    sdna_info
+        .structs
+        .iter()
+        .position(|s| s.type_idx == type_idx)
+}
+
+/// Computes `struct_index`'s real C layout, recursing into member structs so
+/// pointers nested inside embedded structs are found too. Guards against infinite
+/// recursion on self-referential structs via `in_progress`: recursion only ever
+/// happens through value members, since pointer-to-struct members stop recursion
+/// and are scrubbed as a single opaque slot, so a struct that only refers to itself
+/// through a pointer never re-enters here.
+fn compute_struct_layout(
+    struct_index: usize,
+    sdna_info: &DNAInfo,
+    ptr_size: usize,
+    re: &Regex,
+    cache: &mut HashMap<usize, StructLayout>,
+    in_progress: &mut std::collections::HashSet<usize>,
+) -> StructLayout {
+    if let Some(layout) = cache.get(&struct_index) {
+        return layout.clone();
+    }
+
+    if !in_progress.insert(struct_index) {
+        return StructLayout {
+            size: 0,
+            alignment: 1,
+            pointer_offsets: vec![],
+        };
+    }
+
+    let dna_struct = &sdna_info.structs[struct_index];
+    let mut offset: usize = 0;
+    let mut struct_alignment: usize = 1;
+    let mut pointer_offsets: Vec<usize> = vec![];
+
+    for field in &dna_struct.fields {
+        let name = sdna_info.names[field.name_idx as usize].clone();
+        let field_type = parse_field_type(&name, re);
+        let size_from_sdna = sdna_info.type_lengths[field.type_idx as usize] as usize;
+        let member_struct_index = find_struct_index_by_type_idx(sdna_info, field.type_idx);
+
+        let (field_size, field_alignment, field_pointer_offsets) = match &field_type {
+            FieldType::FnPointer => (ptr_size, ptr_size, vec![]),
+            FieldType::Pointer { dimensions } => {
+                let count = count_from_dimensions(dimensions);
+                let pointer_offsets = (0..count).map(|i| i * ptr_size).collect();
+                (count * ptr_size, ptr_size, pointer_offsets)
+            }
+            FieldType::Value => match member_struct_index {
+                Some(inner_index) => {
+                    let inner = compute_struct_layout(
+                        inner_index,
+                        sdna_info,
+                        ptr_size,
+                        re,
+                        cache,
+                        in_progress,
+                    );
+                    (inner.size, inner.alignment, inner.pointer_offsets)
+                }
+                None => {
+                    let alignment = size_from_sdna.min(ptr_size).max(1);
+                    (size_from_sdna, alignment, vec![])
+                }
+            },
+            FieldType::ValueArray { dimensions } => {
+                let count = count_from_dimensions(dimensions);
+                match member_struct_index {
+                    Some(inner_index) => {
+                        let inner = compute_struct_layout(
+                            inner_index,
+                            sdna_info,
+                            ptr_size,
+                            re,
+                            cache,
+                            in_progress,
+                        );
+                        let pointer_offsets = (0..count)
+                            .flat_map(|i| inner.pointer_offsets.iter().map(move |p| i * inner.size + p))
+                            .collect();
+                        (inner.size * count, inner.alignment, pointer_offsets)
+                    }
+                    None => {
+                        let alignment = size_from_sdna.min(ptr_size).max(1);
+                        (size_from_sdna * count, alignment, vec![])
+                    }
+                }
+            }
+        };
+
+        let field_offset = align_up(offset, field_alignment.max(1));
+        pointer_offsets.extend(field_pointer_offsets.into_iter().map(|p| field_offset + p));
+
+        struct_alignment = struct_alignment.max(field_alignment.max(1));
+        offset = field_offset + field_size;
+    }
+
+    // Trailing padding, so this struct's own size keeps later elements aligned when
+    // it is itself an array element or a member of another struct.
+    let size = align_up(offset, struct_alignment);
+
+    in_progress.remove(&struct_index);
+
+    let layout = StructLayout {
+        size,
+        alignment: struct_alignment,
+        pointer_offsets,
+    };
+    cache.insert(struct_index, layout.clone());
+    layout
+}
+
 pub fn make_field_meta_lookup(sdna_info: &DNAInfo, pointer_size: PointerSize) -> FieldMetaLookup {
     let mut result: HashMap<u32, Vec<usize>> = HashMap::new();
     let ptr_size = match pointer_size {
@@ -590,35 +823,346 @@ pub fn make_field_meta_lookup(sdna_info: &DNAInfo, pointer_size: PointerSize) ->
     };
 
     let re = Regex::new(r"\[(\d+)\]").unwrap();
+    let mut cache: HashMap<usize, StructLayout> = HashMap::new();
 
-    for (index, dna_struct) in sdna_info.structs.iter().enumerate() {
-        let mut offset: usize = 0;
-        let mut ptr_offsets: Vec<usize> = vec![];
+    for index in 0..sdna_info.structs.len() {
+        let mut in_progress = std::collections::HashSet::new();
+        let layout =
+            compute_struct_layout(index, sdna_info, ptr_size, &re, &mut cache, &mut in_progress);
 
-        for field in &dna_struct.fields {
-            let name = sdna_info.names[field.name_idx as usize].clone();
-            let field_type = parse_field_type(&name, &re);
-            let size_from_sdna = sdna_info.type_lengths[field.type_idx as usize];
-            let size = match &field_type {
-                FieldType::Value => size_from_sdna as usize,
-                FieldType::ValueArray { dimensions } => {
-                    count_from_dimensions(dimensions) * size_from_sdna as usize
-                }
-                FieldType::FnPointer => ptr_size,
-                FieldType::Pointer { .. } => ptr_size,
+        if !layout.pointer_offsets.is_empty() {
+            result.insert(index as u32, layout.pointer_offsets.clone());
+        }
+    }
+
+    result
+}
+
+/// An address-independent, decoded view of one block's fields. Two revisions of
+/// the "same" block can be diffed field-by-field against this instead of against
+/// raw bytes, which differ wherever Blender happened to reorder allocations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StructuredValue {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Bytes of a scalar type this decoder doesn't recognize.
+    Bytes(Vec<u8>),
+    /// A pointer field's decoded value. The memory address itself never enters the
+    /// value tree — only that a reference exists here — since addresses are exactly
+    /// the noise a content timeline needs to ignore.
+    Reference,
+    Sequence(Vec<StructuredValue>),
+    Struct(HashMap<String, StructuredValue>),
+}
+
+/// Strips DNA field-name decoration (`*`, `**`, `(*...)`, `[n]` suffixes) down to
+/// the bare identifier, for use as a [`StructuredValue::Struct`] key.
+fn clean_field_name(name: &str) -> String {
+    name.chars()
+        .skip_while(|c| !c.is_alphabetic() && *c != '_')
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect()
+}
+
+fn decode_scalar(type_name: &str, bytes: &[u8], endianness: Endianness) -> StructuredValue {
+    match (type_name, bytes.len()) {
+        ("float", 4) => {
+            let b: [u8; 4] = bytes.try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => f32::from_le_bytes(b),
+                Endianness::Big => f32::from_be_bytes(b),
             };
+            StructuredValue::Float(value as f64)
+        }
+        ("double", 8) => {
+            let b: [u8; 8] = bytes.try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => f64::from_le_bytes(b),
+                Endianness::Big => f64::from_be_bytes(b),
+            };
+            StructuredValue::Float(value)
+        }
+        (_, 1) => StructuredValue::Int(bytes[0] as i64),
+        (_, 2) => {
+            let b: [u8; 2] = bytes.try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => i16::from_le_bytes(b),
+                Endianness::Big => i16::from_be_bytes(b),
+            };
+            StructuredValue::Int(value as i64)
+        }
+        (_, 4) => {
+            let b: [u8; 4] = bytes.try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => i32::from_le_bytes(b),
+                Endianness::Big => i32::from_be_bytes(b),
+            };
+            StructuredValue::Int(value as i64)
+        }
+        (_, 8) => {
+            let b: [u8; 8] = bytes.try_into().unwrap();
+            let value = match endianness {
+                Endianness::Little => i64::from_le_bytes(b),
+                Endianness::Big => i64::from_be_bytes(b),
+            };
+            StructuredValue::Int(value)
+        }
+        _ => StructuredValue::Bytes(bytes.to_vec()),
+    }
+}
 
-            if is_pointer(&name) {
-                ptr_offsets.push(offset);
+#[allow(clippy::too_many_arguments)]
+fn decode_struct(
+    struct_index: usize,
+    data: &[u8],
+    base_offset: usize,
+    sdna_info: &DNAInfo,
+    ptr_size: usize,
+    endianness: Endianness,
+    re: &Regex,
+    layouts: &mut HashMap<usize, StructLayout>,
+    in_progress: &mut std::collections::HashSet<usize>,
+) -> StructuredValue {
+    let dna_struct = &sdna_info.structs[struct_index];
+    let mut offset = base_offset;
+    let mut fields = HashMap::new();
+
+    for field in &dna_struct.fields {
+        let name = sdna_info.names[field.name_idx as usize].clone();
+        let type_name = sdna_info.types[field.type_idx as usize].clone();
+        let field_type = parse_field_type(&name, re);
+        let size_from_sdna = sdna_info.type_lengths[field.type_idx as usize] as usize;
+        let member_struct_index = find_struct_index_by_type_idx(sdna_info, field.type_idx);
+        let key = clean_field_name(&name);
+
+        let (field_size, field_alignment) = match &field_type {
+            FieldType::FnPointer => (ptr_size, ptr_size),
+            FieldType::Pointer { dimensions } => {
+                (count_from_dimensions(dimensions).max(1) * ptr_size, ptr_size)
             }
+            FieldType::Value => match member_struct_index {
+                Some(inner_index) => {
+                    let inner =
+                        compute_struct_layout(inner_index, sdna_info, ptr_size, re, layouts, in_progress);
+                    (inner.size, inner.alignment)
+                }
+                None => (size_from_sdna, size_from_sdna.min(ptr_size).max(1)),
+            },
+            FieldType::ValueArray { dimensions } => {
+                let count = count_from_dimensions(dimensions);
+                match member_struct_index {
+                    Some(inner_index) => {
+                        let inner = compute_struct_layout(
+                            inner_index,
+                            sdna_info,
+                            ptr_size,
+                            re,
+                            layouts,
+                            in_progress,
+                        );
+                        (inner.size * count, inner.alignment)
+                    }
+                    None => (size_from_sdna * count, size_from_sdna.min(ptr_size).max(1)),
+                }
+            }
+        };
 
-            offset += size;
+        let field_offset = align_up(offset, field_alignment.max(1));
+        let field_bytes = data.get(field_offset..field_offset + field_size);
+
+        let value = match field_bytes {
+            None => StructuredValue::Bytes(vec![]),
+            Some(bytes) => match &field_type {
+                FieldType::FnPointer => StructuredValue::Reference,
+                FieldType::Pointer { dimensions } => {
+                    if dimensions.is_empty() {
+                        StructuredValue::Reference
+                    } else {
+                        let count = count_from_dimensions(dimensions);
+                        StructuredValue::Sequence(vec![StructuredValue::Reference; count])
+                    }
+                }
+                FieldType::Value => match member_struct_index {
+                    Some(inner_index) => decode_struct(
+                        inner_index,
+                        data,
+                        field_offset,
+                        sdna_info,
+                        ptr_size,
+                        endianness,
+                        re,
+                        layouts,
+                        in_progress,
+                    ),
+                    None => decode_scalar(&type_name, bytes, endianness),
+                },
+                FieldType::ValueArray { dimensions } => {
+                    let count = count_from_dimensions(dimensions);
+                    if type_name == "char" {
+                        // Blender's DNA convention: a `char` array field is a
+                        // null-terminated C string, not a sequence of bytes.
+                        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                        StructuredValue::Str(String::from_utf8_lossy(&bytes[..end]).into_owned())
+                    } else if let Some(inner_index) = member_struct_index {
+                        let inner = compute_struct_layout(
+                            inner_index,
+                            sdna_info,
+                            ptr_size,
+                            re,
+                            layouts,
+                            in_progress,
+                        );
+                        let elements = (0..count)
+                            .map(|i| {
+                                decode_struct(
+                                    inner_index,
+                                    data,
+                                    field_offset + i * inner.size,
+                                    sdna_info,
+                                    ptr_size,
+                                    endianness,
+                                    re,
+                                    layouts,
+                                    in_progress,
+                                )
+                            })
+                            .collect();
+                        StructuredValue::Sequence(elements)
+                    } else {
+                        let elem_size = size_from_sdna;
+                        let elements = (0..count)
+                            .map(|i| {
+                                let lo = i * elem_size;
+                                decode_scalar(&type_name, &bytes[lo..lo + elem_size], endianness)
+                            })
+                            .collect();
+                        StructuredValue::Sequence(elements)
+                    }
+                }
+            },
+        };
+
+        fields.insert(key, value);
+        offset = field_offset + field_size;
+    }
+
+    StructuredValue::Struct(fields)
+}
+
+/// Decodes a block into an address-independent [`StructuredValue`] tree, using
+/// `sdna_info` to resolve each field's name, type, and (for nested/array-of-struct
+/// members) layout. `block.dna_index` is used directly as a struct index, the same
+/// convention [`make_field_meta_lookup`]'s output is keyed by.
+pub fn decode_block(
+    block: &SimpleParsedBlock,
+    sdna_info: &DNAInfo,
+    pointer_size: PointerSize,
+    endianness: Endianness,
+) -> StructuredValue {
+    let ptr_size = match pointer_size {
+        PointerSize::Bits32 => 4,
+        PointerSize::Bits64 => 8,
+    };
+    let re = Regex::new(r"\[(\d+)\]").unwrap();
+    let mut layouts: HashMap<usize, StructLayout> = HashMap::new();
+    let mut in_progress = std::collections::HashSet::new();
+
+    decode_struct(
+        block.dna_index as usize,
+        &block.data,
+        0,
+        sdna_info,
+        ptr_size,
+        endianness,
+        &re,
+        &mut layouts,
+        &mut in_progress,
+    )
+}
+
+/// 256-bit content hash of a block's position-independent ("scrubbed") form: its
+/// type/shape (`code`, `dna_index`, `count`) plus payload bytes with every pointer
+/// and the memory address already zeroed by [`scrub_block`]. Identical scrubbed
+/// blocks across revisions hash the same no matter where Blender happened to place
+/// them in memory, which is what makes this usable as a content-addressable
+/// storage key: a revision need only store the list of block hashes it's made of.
+pub fn block_content_hash(block: &SimpleParsedBlock) -> String {
+    let mut hasher = blake2b_simd::Params::new().hash_length(32).to_state();
+    hasher.update(&block.code);
+    hasher.update(&block.dna_index.to_le_bytes());
+    hasher.update(&block.count.to_le_bytes());
+    hasher.update(&block.data);
+    hasher.finalize().to_hex().to_string()
+}
+
+/// The result of [`diff`]ing two revisions: blocks present only in the new file,
+/// only in the old file, and pairs of (old hash, new hash) for blocks that occupy
+/// the same conceptual slot (matched by `code`/`dna_index`) but whose content hash
+/// changed.
+#[derive(Debug, Clone, Default)]
+pub struct BlendDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<(String, String)>,
+}
+
+/// Diffs two parsed blend files by content hash. Blocks sharing a hash are
+/// untouched and need not be considered further. Among the rest, blocks are paired
+/// up by `(code, dna_index)` — Blender's closest thing to a type tag — so a block
+/// whose content changed is reported as `changed` rather than as an unrelated
+/// add+remove pair.
+pub fn diff(old: &BlendFileWithPointerData, new: &BlendFileWithPointerData) -> BlendDelta {
+    let old_hashes: std::collections::HashSet<String> = old
+        .blocks
+        .iter()
+        .map(|b| block_content_hash(&b.simple_block))
+        .collect();
+    let new_hashes: std::collections::HashSet<String> = new
+        .blocks
+        .iter()
+        .map(|b| block_content_hash(&b.simple_block))
+        .collect();
+
+    let mut only_old: HashMap<(Vec<u8>, u32), Vec<String>> = HashMap::new();
+    for block in &old.blocks {
+        let hash = block_content_hash(&block.simple_block);
+        if !new_hashes.contains(&hash) {
+            only_old
+                .entry((block.simple_block.code.to_vec(), block.simple_block.dna_index))
+                .or_default()
+                .push(hash);
         }
+    }
 
-        if !ptr_offsets.is_empty() {
-            result.insert(index as u32, ptr_offsets);
+    let mut only_new: HashMap<(Vec<u8>, u32), Vec<String>> = HashMap::new();
+    for block in &new.blocks {
+        let hash = block_content_hash(&block.simple_block);
+        if !old_hashes.contains(&hash) {
+            only_new
+                .entry((block.simple_block.code.to_vec(), block.simple_block.dna_index))
+                .or_default()
+                .push(hash);
         }
     }
 
-    result
+    let mut delta = BlendDelta::default();
+    let keys: std::collections::HashSet<_> = only_old
+        .keys()
+        .chain(only_new.keys())
+        .cloned()
+        .collect();
+
+    for key in keys {
+        let mut old_group = only_old.remove(&key).unwrap_or_default();
+        let mut new_group = only_new.remove(&key).unwrap_or_default();
+
+        while let (Some(old_hash), Some(new_hash)) = (old_group.pop(), new_group.pop()) {
+            delta.changed.push((old_hash, new_hash));
+        }
+        delta.removed.extend(old_group);
+        delta.added.extend(new_group);
+    }
+
+    delta
 }
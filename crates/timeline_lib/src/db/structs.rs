@@ -17,4 +17,28 @@ pub struct Commit {
     pub date: u64,
     pub header: Vec<u8>,
     pub blocks: String,
+    /// Ed25519 public key of the author that signed this commit. Empty when the
+    /// commit is unsigned, which existing (pre-signing) databases and peers are.
+    pub author_pubkey: Vec<u8>,
+    /// Ed25519 signature over `signing::canonical_commit_hash(&commit)`. Empty when
+    /// the commit is unsigned.
+    pub signature: Vec<u8>,
+}
+
+/// [`Commit`] without `blocks` -- the working-dir blob that lives in `kv`, not the
+/// relational `commits` table. A caller that only needs metadata (branch listings,
+/// graph rendering, existence checks) can read a batch of these with a single SQLite
+/// scan instead of one `kv` point lookup per commit.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+pub struct ShortCommit {
+    pub hash: String,
+    pub prev_commit_hash: String,
+    pub project_id: String,
+    pub branch: String,
+    pub message: String,
+    pub author: String,
+    pub date: u64,
+    pub header: Vec<u8>,
+    pub author_pubkey: Vec<u8>,
+    pub signature: Vec<u8>,
 }
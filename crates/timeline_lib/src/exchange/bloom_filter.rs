@@ -0,0 +1,118 @@
+use sha2::{Digest, Sha512};
+
+/// Space-efficient, approximate set-membership test: a client builds one of these
+/// over every block hash it already stores and sends it along with a sync request so
+/// the server can skip re-sending blocks the client almost certainly already has.
+/// False positives are possible (a present block reported as maybe-missing never
+/// happens; a missing block occasionally gets reported as present) -- callers that
+/// can't tolerate a skipped block should follow up with an explicit `/fetch-blocks`
+/// request for anything they find they're missing.
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: usize,
+    num_hashes: usize,
+}
+
+impl BloomFilter {
+    /// Sizes the filter for `expected_items` entries at a ~1% false-positive rate:
+    /// `m = -n*ln(p)/(ln2)^2` bits, `k = (m/n)*ln2` hash functions.
+    pub fn with_expected_items(expected_items: usize) -> BloomFilter {
+        let n = (expected_items.max(1)) as f64;
+        let p = 0.01_f64;
+        let ln2 = std::f64::consts::LN_2;
+
+        let num_bits = ((-(n * p.ln())) / (ln2 * ln2)).ceil().max(1.0) as usize;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as usize;
+
+        BloomFilter {
+            bits: vec![0u64; (num_bits + 63) / 64],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Builds a filter sized for the given hashes and inserts every one of them.
+    pub fn build<'a>(hashes: impl Iterator<Item = &'a String>) -> BloomFilter {
+        let hashes: Vec<&String> = hashes.collect();
+        let mut filter = BloomFilter::with_expected_items(hashes.len());
+        for hash in hashes {
+            filter.insert(hash);
+        }
+        filter
+    }
+
+    /// Derives `num_hashes` bit indices from the first 8 bytes of a SHA-512 digest of
+    /// `hash_hex`, instead of running `num_hashes` independent hash functions.
+    fn indices(&self, hash_hex: &str) -> Vec<usize> {
+        let digest = Sha512::digest(hash_hex.as_bytes());
+        let mut state = u64::from_be_bytes(digest[0..8].try_into().unwrap());
+
+        (0..self.num_hashes)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % self.num_bits as u64) as usize
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, hash_hex: &str) {
+        for index in self.indices(hash_hex) {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    /// `false` means `hash_hex` is definitely absent; `true` means it's present or a
+    /// false positive.
+    pub fn might_contain(&self, hash_hex: &str) -> bool {
+        self.indices(hash_hex)
+            .into_iter()
+            .all(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.bits.len() * 8);
+        out.extend_from_slice(&(self.num_bits as u64).to_be_bytes());
+        out.extend_from_slice(&(self.num_hashes as u64).to_be_bytes());
+        for word in &self.bits {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<BloomFilter> {
+        if bytes.len() < 16 {
+            return None;
+        }
+        let num_bits = u64::from_be_bytes(bytes[0..8].try_into().ok()?) as usize;
+        let num_hashes = u64::from_be_bytes(bytes[8..16].try_into().ok()?) as usize;
+        let bits: Vec<u64> = bytes[16..]
+            .chunks_exact(8)
+            .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Some(BloomFilter {
+            bits,
+            num_bits,
+            num_hashes,
+        })
+    }
+
+    /// Hex encoding of `to_bytes`, for carrying the filter over a header or other
+    /// text-only channel.
+    pub fn to_hex(&self) -> String {
+        self.to_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Option<BloomFilter> {
+        if hex.len() % 2 != 0 {
+            return None;
+        }
+        let bytes: Option<Vec<u8>> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect();
+        BloomFilter::from_bytes(&bytes?)
+    }
+}
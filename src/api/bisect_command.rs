@@ -0,0 +1,136 @@
+use anyhow::bail;
+
+use crate::db::db_ops::{BisectState, DBError, Persistence, DB};
+
+use super::restore_command::restore_checkpoint;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BisectVerdict {
+    Good,
+    Bad,
+}
+
+#[derive(Debug, Clone)]
+pub enum BisectStep {
+    /// The midpoint checkpoint has been restored into the working `.blend`; the
+    /// caller should test it and report back with `good` or `bad`.
+    InProgress { checkpoint_hash: String },
+    /// Exactly one candidate remains: the first checkpoint at which the regression
+    /// appeared.
+    Done { first_bad_checkpoint: String },
+}
+
+fn midpoint(low: usize, high: usize) -> usize {
+    low + (high - low) / 2
+}
+
+/// Restores `candidates[mid]` and reports it, or -- if the bounds have already
+/// converged -- reports the answer without touching the working file.
+fn advance(
+    conn: &mut Persistence,
+    db_path: &str,
+    file_path: &str,
+    encryption_key: Option<&[u8; 32]>,
+    state: BisectState,
+) -> anyhow::Result<BisectStep> {
+    if state.high - state.low <= 1 {
+        let first_bad_checkpoint = state.candidates[state.high].clone();
+        conn.execute_in_transaction(|tx| Persistence::clear_bisect_state(tx))?;
+        return Ok(BisectStep::Done {
+            first_bad_checkpoint,
+        });
+    }
+
+    let mid = midpoint(state.low, state.high);
+    let checkpoint_hash = state.candidates[mid].clone();
+
+    restore_checkpoint(file_path, db_path, &checkpoint_hash, encryption_key)?;
+
+    conn.execute_in_transaction(|tx| Persistence::write_bisect_state(tx, &state))?;
+
+    Ok(BisectStep::InProgress { checkpoint_hash })
+}
+
+/// Starts a bisect session between a known-good and a known-bad commit on the same
+/// ancestry. Walks `prev_commit_hash` back from `bad_hash` to build the ordered
+/// candidate list, erroring if `good_hash` never shows up (i.e. isn't an ancestor of
+/// `bad_hash`), then restores the midpoint checkpoint and persists the session's
+/// `[low, high]` bounds so [`step_bisect`] can pick it back up on the next call.
+pub fn start_bisect(
+    db_path: &str,
+    file_path: &str,
+    good_hash: &str,
+    bad_hash: &str,
+    encryption_key: Option<&[u8; 32]>,
+) -> anyhow::Result<BisectStep> {
+    let mut conn = Persistence::open(db_path)?;
+
+    // Most recent first: `bad_hash` is the head, the root is the tail.
+    let descending = conn.read_ancestors_of_commit(bad_hash)?;
+
+    let good_index = descending
+        .iter()
+        .position(|commit| commit.hash == good_hash)
+        .ok_or_else(|| {
+            DBError::Error(format!(
+                "{} is not an ancestor of {}, cannot bisect",
+                good_hash, bad_hash
+            ))
+        })?;
+
+    // Reverse to ascending chronological order: candidates[0] is good, the last
+    // entry is bad.
+    let candidates: Vec<String> = descending[..=good_index]
+        .iter()
+        .rev()
+        .map(|commit| commit.hash.clone())
+        .collect();
+
+    let state = BisectState {
+        low: 0,
+        high: candidates.len() - 1,
+        candidates,
+    };
+
+    advance(&mut conn, db_path, file_path, encryption_key, state)
+}
+
+/// Advances an in-progress bisect session with the caller's verdict on the last
+/// restored checkpoint, restoring the next midpoint (or, once the bounds converge to
+/// a single commit, reporting that commit as the answer and clearing the session).
+pub fn step_bisect(
+    db_path: &str,
+    file_path: &str,
+    verdict: BisectVerdict,
+    encryption_key: Option<&[u8; 32]>,
+) -> anyhow::Result<BisectStep> {
+    let mut conn = Persistence::open(db_path)?;
+
+    let state = conn
+        .read_bisect_state()?
+        .ok_or_else(|| DBError::Error("No bisect session in progress".to_owned()))?;
+
+    if state.high - state.low <= 1 {
+        bail!(DBError::Consistency(
+            "Bisect session already converged, start a new one".to_owned(),
+        ));
+    }
+
+    let mid = midpoint(state.low, state.high);
+    let (low, high) = match verdict {
+        BisectVerdict::Bad => (state.low, mid),
+        BisectVerdict::Good => (mid, state.high),
+    };
+
+    advance(
+        &mut conn,
+        db_path,
+        file_path,
+        encryption_key,
+        BisectState {
+            candidates: state.candidates,
+            low,
+            high,
+        },
+    )
+}
@@ -3,10 +3,13 @@ use anyhow::bail;
 use crate::db::db_ops::{DBError, Persistence, DB};
 use std::path::Path;
 
-use super::restore_command::restore_checkpoint;
+use super::{common::resolve_encryption_key, restore_command::restore_checkpoint};
 
-pub fn blend_file_from_timeline(db_path: &str) -> anyhow::Result<String> {
-    let conn = Persistence::open(db_path)?;
+pub fn blend_file_from_timeline(
+    db_path: &str,
+    passphrase: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut conn = Persistence::open(db_path)?;
     let tip = conn.read_branch_tip("main")?;
     if tip.is_none() {
         bail!(DBError::Consistency(String::from(
@@ -27,7 +30,8 @@ pub fn blend_file_from_timeline(db_path: &str) -> anyhow::Result<String> {
     let blend_file_path = blend_file_path_buf.to_str().unwrap();
     println!("{blend_file_path}");
 
-    restore_checkpoint(blend_file_path, db_path, &tip)?;
+    let encryption_key = resolve_encryption_key(&mut conn, passphrase)?;
+    restore_checkpoint(blend_file_path, db_path, &tip, encryption_key.as_ref())?;
 
     Ok(blend_file_path.to_string())
 }
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use super::init_command::INITIAL_COMMIT_HASH;
+use crate::db::db_ops::{Persistence, DB};
+
+#[derive(Debug, Clone)]
+pub struct CommitNode {
+    pub hash: String,
+    pub message: String,
+    pub author: String,
+    pub date: u64,
+    pub branch: String,
+    /// This commit's `commit_parents` rows, in ordinal order -- one entry for an
+    /// ordinary commit, N for an N-way merge. Empty for the very first commit of the
+    /// project.
+    pub parents: Vec<String>,
+}
+
+/// Collects every commit reachable from any branch tip into a de-duplicated map, the
+/// way `list_checkpoints` does for a single branch, but across all of them at once and
+/// with parent links attached so a client can lay the result out as a DAG instead of a
+/// flat per-branch list.
+pub fn branch_history(db_path: &str) -> anyhow::Result<Vec<CommitNode>> {
+    let conn = Persistence::open(db_path)?;
+
+    let mut nodes: HashMap<String, CommitNode> = HashMap::new();
+
+    for branch in conn.read_all_branches()? {
+        let tip = match conn.read_branch_tip(&branch)? {
+            Some(tip) => tip,
+            None => continue,
+        };
+
+        for short in conn.read_ancestors_of_commit(&tip)? {
+            if nodes.contains_key(&short.hash) {
+                continue;
+            }
+
+            let commit = match conn.read_commit(&short.hash)? {
+                Some(commit) => commit,
+                None => continue,
+            };
+
+            // `commit_parents`, not `prev_commit_hash` + `read_merge_parent`: a merge
+            // made via `create_merge_checkpoint` only ever records its parents in
+            // `commit_parents` (see that function's doc comment), so reading the old
+            // single-second-parent table here would silently drop such a merge's
+            // second branch from the returned DAG.
+            let parents: Vec<String> = conn
+                .read_parents_of_commit(&commit.hash)?
+                .into_iter()
+                .filter(|parent| parent != INITIAL_COMMIT_HASH)
+                .collect();
+
+            nodes.insert(
+                commit.hash.clone(),
+                CommitNode {
+                    hash: commit.hash,
+                    message: commit.message,
+                    author: commit.author,
+                    date: commit.date,
+                    branch: commit.branch,
+                    parents,
+                },
+            );
+        }
+    }
+
+    let mut history: Vec<CommitNode> = nodes.into_values().collect();
+    history.sort_by(|a, b| b.date.cmp(&a.date));
+
+    Ok(history)
+}
@@ -1,15 +1,20 @@
 use blake2b_simd::blake2b;
 use filetime::FileTime;
-use flate2::{write::GzEncoder, Compression};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
 use crate::{
     blend::{
         parse_print_blend::{
             parse_blend, print_block_manual, print_header_manual, OffsetsWithPointerValue,
         },
-        utils::{from_file, Either},
+        utils::{
+            compress_block, compress_block_with_dict, default_kdf_params,
+            derive_key_from_passphrase, encrypt_block, from_file, generate_salt,
+            kdf_params_from_string, kdf_params_to_string, salt_from_hex, salt_to_hex,
+            train_dictionary, EncryptionAlgo, Either,
+        },
     },
     db::{
         db_ops::{DBError, Persistence, DB},
@@ -18,7 +23,7 @@ use crate::{
     measure_time,
 };
 
-use std::{fs, io::Write};
+use std::fs;
 
 pub fn read_latest_commit_hash_on_branch(
     conn: &Persistence,
@@ -31,6 +36,45 @@ pub fn read_latest_commit_hash_on_branch(
     })
 }
 
+/// Derives the at-rest encryption key for a store from a user passphrase, generating
+/// and persisting a random per-store salt on first use. Returns `None` when
+/// `passphrase` is `None`, so encryption stays fully optional.
+pub fn resolve_encryption_key(
+    conn: &mut Persistence,
+    passphrase: Option<&str>,
+) -> anyhow::Result<Option<[u8; 32]>> {
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => return Ok(None),
+    };
+
+    let salt = match conn.read_encryption_salt()? {
+        Some(salt_hex) => salt_from_hex(&salt_hex).map_err(anyhow::Error::new)?,
+        None => {
+            let salt = generate_salt();
+            conn.execute_in_transaction(|tx| {
+                Persistence::write_encryption_salt(tx, &salt_to_hex(&salt))
+            })?;
+            salt.to_vec()
+        }
+    };
+
+    let kdf_params = match conn.read_encryption_kdf_params()? {
+        Some(kdf_params) => kdf_params_from_string(&kdf_params).map_err(anyhow::Error::new)?,
+        None => {
+            let kdf_params = default_kdf_params();
+            conn.execute_in_transaction(|tx| {
+                Persistence::write_encryption_kdf_params(tx, &kdf_params_to_string(&kdf_params))
+            })?;
+            kdf_params
+        }
+    };
+
+    let key =
+        derive_key_from_passphrase(passphrase, &salt, &kdf_params).map_err(anyhow::Error::new)?;
+    Ok(Some(key))
+}
+
 pub fn get_file_mod_time(file_path: &str) -> Result<i64, DBError> {
     let metadata = fs::metadata(file_path)
         .map_err(|e| DBError::Consistency(format!("File {} does not exist ({}))", file_path, e)))?;
@@ -50,14 +94,100 @@ pub struct BlockMetadata {
     pub hash: String,
     pub original_mem_address: Either<u32, u64>,
     pub pointers: OffsetsWithPointerValue, // offset with pointer value
+    /// Ordered content-defined sub-chunk hashes that reassemble into the block's
+    /// uncompressed bytes. Each hash is also the key under which the compressed
+    /// chunk is stored, so identical sub-chunks across blocks/commits are shared.
+    pub chunk_hashes: Vec<String>,
+    /// Hash of the shared zstd dictionary (itself stored as an ordinary block, see
+    /// `BlockRecord`) that this block's chunks were compressed against, if any.
+    pub dictionary_hash: Option<String>,
 }
 
 pub fn get_hash(data: &[u8]) -> String {
     blake2b(data).to_hex().to_string()
 }
 
+// FastCDC-style content-defined chunking, tuned for the block sizes blend files
+// tend to produce (small DNA/struct blocks up to multi-megabyte mesh/image blocks).
+const CDC_MIN_SIZE: usize = 2 * 1024;
+const CDC_AVG_SIZE: usize = 8 * 1024;
+const CDC_MAX_SIZE: usize = 64 * 1024;
+
+// Normalized chunking: a stricter (more one-bits) mask makes cuts rarer before the
+// average size, a looser mask makes them more likely after it, pulling the size
+// distribution tight around `CDC_AVG_SIZE` instead of the wide spread a single
+// mask produces.
+const CDC_MASK_S: u64 = (1u64 << 15) - 1;
+const CDC_MASK_L: u64 = (1u64 << 11) - 1;
+
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        // xorshift64 seeded with a fixed constant: deterministic across runs/machines,
+        // which matters because the table doubles as part of the chunking algorithm.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut table = [0u64; 256];
+        for slot in table.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *slot = state;
+        }
+        table
+    })
+}
+
+/// Splits `data` into content-defined chunks so that edits to a small region of a
+/// large block only change the chunks touching that region, instead of the whole
+/// block's hash.
+pub fn chunk_content_defined(data: &[u8]) -> Vec<&[u8]> {
+    if data.len() <= CDC_MIN_SIZE {
+        return vec![data];
+    }
+
+    let gear = gear_table();
+    let mut chunks = vec![];
+    let mut start = 0usize;
+
+    while start < data.len() {
+        let remaining = data.len() - start;
+        if remaining <= CDC_MIN_SIZE {
+            chunks.push(&data[start..]);
+            break;
+        }
+
+        let mut fp: u64 = 0;
+        let mut offset = CDC_MIN_SIZE;
+        let mut cut = None;
+
+        while offset < CDC_MAX_SIZE && start + offset < data.len() {
+            let byte = data[start + offset];
+            fp = (fp << 1).wrapping_add(gear[byte as usize]);
+
+            let mask = if offset < CDC_AVG_SIZE {
+                CDC_MASK_S
+            } else {
+                CDC_MASK_L
+            };
+
+            if fp & mask == 0 {
+                cut = Some(offset);
+                break;
+            }
+            offset += 1;
+        }
+
+        let chunk_len = cut.unwrap_or_else(|| std::cmp::min(CDC_MAX_SIZE, remaining));
+        chunks.push(&data[start..start + chunk_len]);
+        start += chunk_len;
+    }
+
+    chunks
+}
+
 pub fn blend_file_data_from_file(
     path_to_blend: &str,
+    encryption_key: Option<&[u8; 32]>,
 ) -> Result<BlendFileDataForCheckpoint, String> {
     let exists = std::path::Path::new(path_to_blend).exists();
     if !exists {
@@ -75,8 +205,16 @@ pub fn blend_file_data_from_file(
 
     println!("Number of blocks: {:?}", parsed_blend.blocks.len());
 
-    let block_data_with_meta: Vec<(BlockMetadata, Vec<u8>)> = measure_time!(
-        format!("Hashing and compressing blocks {:?}", path_to_blend),
+    // Chunking and hashing is independent per block, but compression is not: a
+    // shared zstd dictionary can only be trained once every block's chunks are
+    // known, so this happens in two passes.
+    struct ChunkedBlock {
+        meta: BlockMetadata,
+        chunks: Vec<(String, Vec<u8>)>,
+    }
+
+    let chunked_blocks: Vec<ChunkedBlock> = measure_time!(
+        format!("Chunking and hashing blocks {:?}", path_to_blend),
         {
             parsed_blend
                 .blocks
@@ -87,43 +225,90 @@ pub fn blend_file_data_from_file(
 
                     let hash = get_hash(&block_blob);
 
-                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-                    encoder
-                        .write_all(&block_blob)
-                        .map_err(|e| format!("Cannot encode: {:?}", e))?;
-                    let compressed = encoder
-                        .finish()
-                        .map_err(|e| format!("Cannot encode: {:?}", e))?;
+                    let chunks: Vec<(String, Vec<u8>)> = chunk_content_defined(&block_blob)
+                        .into_iter()
+                        .map(|chunk| (get_hash(chunk), chunk.to_vec()))
+                        .collect();
+                    let chunk_hashes = chunks.iter().map(|(hash, _)| hash.clone()).collect();
 
-                    Ok((
-                        BlockMetadata {
+                    ChunkedBlock {
+                        meta: BlockMetadata {
                             hash,
                             original_mem_address: parsed_block.original_mem_address,
                             pointers: parsed_block.pointers,
+                            chunk_hashes,
+                            dictionary_hash: None,
                         },
-                        compressed,
-                    ))
+                        chunks,
+                    }
+                })
+                .collect()
+        }
+    );
+
+    // Many blocks share identical sub-chunks (e.g. structurally similar small
+    // blocks), so de-dup by hash before training/compressing.
+    let mut unique_chunks: std::collections::HashMap<String, Vec<u8>> =
+        std::collections::HashMap::new();
+    for block in &chunked_blocks {
+        for (chunk_hash, chunk_bytes) in &block.chunks {
+            unique_chunks
+                .entry(chunk_hash.clone())
+                .or_insert_with(|| chunk_bytes.clone());
+        }
+    }
+
+    // Small, structurally similar blocks (DNA/struct headers etc.) compress far
+    // better against a shared dictionary than independently, so train one over a
+    // sample of this checkpoint's unique chunks. `train_dictionary` returns `None`
+    // (falling back to plain per-chunk compression) when there isn't enough data to
+    // make training worthwhile.
+    let dictionary: Option<Vec<u8>> =
+        train_dictionary(&unique_chunks.values().cloned().collect::<Vec<_>>());
+
+    // Encryption, when enabled, always runs after compression — encrypted bytes are
+    // indistinguishable from random and would not compress.
+    let maybe_encrypt = |data: Vec<u8>| match encryption_key {
+        Some(key) => encrypt_block(&data, key, EncryptionAlgo::ChaCha20Poly1305),
+        None => data,
+    };
+
+    let dictionary_record = dictionary.as_ref().map(|dict| BlockRecord {
+        hash: get_hash(dict),
+        data: maybe_encrypt(compress_block(dict)),
+    });
+    let dictionary_hash = dictionary_record.as_ref().map(|record| record.hash.clone());
+
+    let mut block_records: Vec<BlockRecord> = measure_time!(
+        format!("Compressing blocks {:?}", path_to_blend),
+        {
+            unique_chunks
+                .into_par_iter()
+                .map(|(hash, bytes)| {
+                    let compressed = match &dictionary {
+                        Some(dict) => compress_block_with_dict(&bytes, dict),
+                        None => compress_block(&bytes),
+                    };
+                    let data = maybe_encrypt(compressed);
+                    BlockRecord { hash, data }
                 })
-                .collect::<Vec<Result<(BlockMetadata, Vec<u8>), String>>>()
-                .into_iter()
-                .collect::<Result<Vec<(BlockMetadata, Vec<u8>)>, String>>()
+                .collect()
         }
-    )?;
+    );
+
+    if let Some(record) = dictionary_record {
+        block_records.push(record);
+    }
 
     let mut header_data: Vec<u8> = vec![];
     print_header_manual(parsed_blend.header, &mut header_data);
 
-    let block_records: Vec<BlockRecord> = block_data_with_meta
-        .par_iter()
-        .map(|(meta, data)| BlockRecord {
-            hash: meta.hash.clone(),
-            data: data.to_owned(),
-        })
-        .collect();
-
-    let blocks_meta: Vec<BlockMetadata> = block_data_with_meta
+    let blocks_meta: Vec<BlockMetadata> = chunked_blocks
         .into_iter()
-        .map(|(meta, _)| meta)
+        .map(|block| BlockMetadata {
+            dictionary_hash: dictionary_hash.clone(),
+            ..block.meta
+        })
         .collect();
 
     let block_meta_bytes = print_blocks_and_pointers(blocks_meta);
@@ -4,11 +4,13 @@ use crate::{
     api::{
         common::{
             blend_file_data_from_file, get_file_mod_time, parse_blocks_and_pointers,
-            read_latest_commit_hash_on_branch,
+            read_latest_commit_hash_on_branch, resolve_encryption_key,
         },
+        signing::{sign_commit_hash, signing_key_from_env},
         utils::{block_hash_diff, get_file_size_str},
     },
     db::{
+        block_store::open_block_store,
         db_ops::{DBError, Persistence, DB},
         structs::Commit,
     },
@@ -21,6 +23,7 @@ pub fn create_new_checkpoint(
     file_path: &str,
     db_path: &str,
     message: Option<String>,
+    passphrase: Option<&str>,
 ) -> anyhow::Result<()> {
     let mut conn = Persistence::open(db_path)?;
 
@@ -31,7 +34,8 @@ pub fn create_new_checkpoint(
     );
 
     let start_checkpoint_command = Instant::now();
-    let blend_data = blend_file_data_from_file(file_path)
+    let encryption_key = resolve_encryption_key(&mut conn, passphrase)?;
+    let blend_data = blend_file_data_from_file(file_path, encryption_key.as_ref())
         .map_err(|e| DBError::Error(format!("Error parsing blend file: {}", e)))?;
 
     let hash_already_exists = conn.check_commit_exists(&blend_data.hash)?;
@@ -78,10 +82,28 @@ pub fn create_new_checkpoint(
 
     let name = conn.read_name()?.unwrap_or("".to_owned());
 
+    let mut all_hashes_in_commit: Vec<String> = Vec::new();
+    for meta in parse_blocks_and_pointers(&blend_data.blocks_and_pointers_bytes) {
+        all_hashes_in_commit.extend(meta.chunk_hashes);
+        if let Some(dictionary_hash) = meta.dictionary_hash {
+            all_hashes_in_commit.push(dictionary_hash);
+        }
+    }
+
+    // Block storage is pluggable (see `db::block_store`), so it's written through its
+    // own driver rather than inside the relational `execute_in_transaction` call below.
+    // Refcounts are bumped alongside the write for the same reason: blocks shared with
+    // an earlier commit are deduplicated away from `new_blocks_since_latest`, but this
+    // commit still holds a reference to them that garbage collection must account for.
+    measure_time!(format!("Writing blocks {:?}", file_path), {
+        let block_store = open_block_store(db_path)?;
+        block_store.write_blocks(&new_blocks_since_latest[..])?;
+        block_store.increment_refcounts(&all_hashes_in_commit)?;
+    });
+
+    let commit_hash = blend_data.hash.clone();
+
     conn.execute_in_transaction(|tx| {
-        measure_time!(format!("Writing blocks {:?}", file_path), {
-            Persistence::write_blocks(tx, &new_blocks_since_latest[..])?
-        });
         Persistence::write_branch_tip(tx, &current_branch_name, &blend_data.hash)?;
         Persistence::write_last_modifiction_time(tx, file_last_mod_time)?;
         Persistence::write_current_commit_pointer(tx, &blend_data.hash)?;
@@ -97,7 +119,17 @@ pub fn create_new_checkpoint(
             header: blend_data.header_bytes,
             blocks_and_pointers: blend_data.blocks_and_pointers_bytes,
         };
-        Persistence::write_commit(tx, commit)
+        Persistence::write_commit(tx, commit)?;
+
+        // Signing is opt-in: only runs when `COMMIT_SIGNING_SEED` is set, so a
+        // project that never configures it keeps writing unsigned commits exactly
+        // as before.
+        if let Some(signing_key) = signing_key_from_env() {
+            let (author_pubkey, signature) = sign_commit_hash(&commit_hash, &signing_key);
+            Persistence::write_commit_signature(tx, &commit_hash, &author_pubkey, &signature)?;
+        }
+
+        Ok(())
     })?;
 
     println!(
@@ -154,19 +186,21 @@ mod test {
             "data/fixtures/untitled_2.blend",
             tmp_path,
             Some("Initial checkpoint".to_owned()),
+            None,
         )
         .unwrap();
 
-        // Creates exactly one commit
+        // Creates exactly one commit, appended after the initial one -- `list_checkpoints`
+        // now emits parents before children, so the initial commit comes first.
         insta::assert_debug_snapshot!(test_utils::list_checkpoints(tmp_path, MAIN_BRANCH_NAME), @r###"
         [
             ShortCommitRecord {
-                hash: "94ab91e7ea864efd6cc228472d47d2a1ca648682ff25cbcb79a9d7a286811fb61d75bee6964aaeec2850f881f8b924dc88b626af405d0ffe813596c4f5033f84",
+                hash: "74ae7a3e82bc3106ae7c510c7c75f9ec704c96a9d9f2bb2ed889f38ff2c0ead2f349aeb43aba7ddb435c8ba8b2ffdd00406ec41bb3c3b0092e6f5062852c542d",
                 branch: "main",
                 message: "Initial checkpoint",
             },
             ShortCommitRecord {
-                hash: "74ae7a3e82bc3106ae7c510c7c75f9ec704c96a9d9f2bb2ed889f38ff2c0ead2f349aeb43aba7ddb435c8ba8b2ffdd00406ec41bb3c3b0092e6f5062852c542d",
+                hash: "94ab91e7ea864efd6cc228472d47d2a1ca648682ff25cbcb79a9d7a286811fb61d75bee6964aaeec2850f881f8b924dc88b626af405d0ffe813596c4f5033f84",
                 branch: "main",
                 message: "Initial checkpoint",
             },
@@ -219,12 +253,14 @@ mod test {
             "data/fixtures/untitled_2.blend",
             tmp_path,
             Some("Message".to_owned()),
+            None,
         )
         .unwrap();
         create_new_checkpoint(
             "data/fixtures/untitled_3.blend",
             tmp_path,
             Some("Message".to_owned()),
+            None,
         )
         .unwrap();
 
@@ -233,6 +269,7 @@ mod test {
             3
         );
 
+        // `list_checkpoints` emits parents before children, so this is oldest-first.
         insta::assert_debug_snapshot!(
             test_utils::list_checkpoints(tmp_path, MAIN_BRANCH_NAME)
                 .into_iter()
@@ -240,9 +277,9 @@ mod test {
                 .collect::<Vec<String>>(),
             @r###"
         [
-            "5e0e611ae1c01a131edd79b57d96d9ca4714a823a567c5fa73f3a973503aa0f6c660f2570ea5d9c04942a3e4ab34d35f71598be62e1cb8a7a40b4826aac4009c",
-            "94ab91e7ea864efd6cc228472d47d2a1ca648682ff25cbcb79a9d7a286811fb61d75bee6964aaeec2850f881f8b924dc88b626af405d0ffe813596c4f5033f84",
             "74ae7a3e82bc3106ae7c510c7c75f9ec704c96a9d9f2bb2ed889f38ff2c0ead2f349aeb43aba7ddb435c8ba8b2ffdd00406ec41bb3c3b0092e6f5062852c542d",
+            "94ab91e7ea864efd6cc228472d47d2a1ca648682ff25cbcb79a9d7a286811fb61d75bee6964aaeec2850f881f8b924dc88b626af405d0ffe813596c4f5033f84",
+            "5e0e611ae1c01a131edd79b57d96d9ca4714a823a567c5fa73f3a973503aa0f6c660f2570ea5d9c04942a3e4ab34d35f71598be62e1cb8a7a40b4826aac4009c",
         ]
         "###
         );
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+
+use crate::db::db_ops::{DBError, Persistence, DB};
+
+use super::common::parse_blocks_and_pointers;
+
+pub struct DiffCheckpointsReport {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub common_count: usize,
+    /// Sum of the stored (compressed) size of every added/removed chunk -- an
+    /// approximation of the changed bytes, not the uncompressed block size.
+    pub approx_changed_bytes: u64,
+}
+
+/// Reports what changed between two checkpoints without restoring either one: loads
+/// each commit's chunk-hash list and classifies every chunk as added, removed, or
+/// common to both, the way `block_hash_diff` dedups new commits against the previous
+/// one, but for an arbitrary pair of checkpoints instead of "current vs. latest".
+pub fn diff_checkpoints(
+    db_path: &str,
+    from_hash: &str,
+    to_hash: &str,
+) -> anyhow::Result<DiffCheckpointsReport> {
+    let conn = Persistence::open(db_path)?;
+
+    let from_commit = conn
+        .read_commit(from_hash)?
+        .ok_or_else(|| DBError::Error(format!("No such checkpoint: {}", from_hash)))?;
+    let to_commit = conn
+        .read_commit(to_hash)?
+        .ok_or_else(|| DBError::Error(format!("No such checkpoint: {}", to_hash)))?;
+
+    let from_hashes: HashSet<String> = parse_blocks_and_pointers(&from_commit.blocks_and_pointers)
+        .into_iter()
+        .flat_map(|meta| meta.chunk_hashes)
+        .collect();
+    let to_hashes: HashSet<String> = parse_blocks_and_pointers(&to_commit.blocks_and_pointers)
+        .into_iter()
+        .flat_map(|meta| meta.chunk_hashes)
+        .collect();
+
+    let added: Vec<String> = to_hashes.difference(&from_hashes).cloned().collect();
+    let removed: Vec<String> = from_hashes.difference(&to_hashes).cloned().collect();
+    let common_count = from_hashes.intersection(&to_hashes).count();
+
+    let mut changed_hashes = added.clone();
+    changed_hashes.extend(removed.iter().cloned());
+
+    let approx_changed_bytes: u64 = conn
+        .read_blocks(changed_hashes)?
+        .iter()
+        .map(|record| record.data.len() as u64)
+        .sum();
+
+    Ok(DiffCheckpointsReport {
+        added,
+        removed,
+        common_count,
+        approx_changed_bytes,
+    })
+}
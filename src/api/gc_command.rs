@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use crate::db::{
+    block_store::is_block_refcount_key,
+    db_ops::{Persistence, DB},
+};
+
+use super::common::parse_blocks_and_pointers;
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub blocks_removed: usize,
+    pub bytes_reclaimed: usize,
+}
+
+fn live_block_hashes(conn: &Persistence, commit_hash: &str) -> anyhow::Result<HashSet<String>> {
+    let mut live = HashSet::new();
+
+    if let Some(commit) = conn.read_commit(commit_hash)? {
+        for meta in parse_blocks_and_pointers(&commit.blocks_and_pointers) {
+            live.extend(meta.chunk_hashes);
+            if let Some(dictionary_hash) = meta.dictionary_hash {
+                live.insert(dictionary_hash);
+            }
+        }
+    }
+
+    Ok(live)
+}
+
+/// Deletes every stored block unreachable from a live root -- every branch tip, plus
+/// the current commit pointer so a detached HEAD isn't collected out from under the
+/// user -- walking `prev_commit_hash` and merge second-parents transitively to gather
+/// every reachable commit before unioning their block hashes into the live set.
+/// `keep_newer_than`, if given, additionally protects every block referenced by a
+/// commit created at or after that Unix timestamp, even if no branch points at it yet
+/// (e.g. a checkpoint written moments ago that hasn't been pushed to a branch tip).
+pub fn gc(db_path: &str, keep_newer_than: Option<i64>) -> anyhow::Result<GcReport> {
+    let mut conn = Persistence::open(db_path)?;
+
+    let mut roots: Vec<String> = Vec::new();
+    for branch in conn.read_all_branches()? {
+        if let Some(tip) = conn.read_branch_tip(&branch)? {
+            roots.push(tip);
+        }
+    }
+    roots.push(conn.read_current_commit_pointer()?);
+
+    let mut live_commits: HashSet<String> = HashSet::new();
+    let mut frontier = roots;
+
+    while let Some(hash) = frontier.pop() {
+        for short in conn.read_ancestors_of_commit(&hash)? {
+            if live_commits.insert(short.hash.clone()) {
+                if let Some(second_parent) = conn.read_merge_parent(&short.hash)? {
+                    frontier.push(second_parent);
+                }
+            }
+        }
+    }
+
+    let mut live_blocks: HashSet<String> = HashSet::new();
+    for commit_hash in &live_commits {
+        live_blocks.extend(live_block_hashes(&conn, commit_hash)?);
+    }
+
+    if let Some(cutoff) = keep_newer_than {
+        for commit in conn.read_commits_since(cutoff)? {
+            live_blocks.extend(live_block_hashes(&conn, &commit.hash)?);
+        }
+    }
+
+    // `read_all_block_hashes` scans the same `blocks` table `SqliteBlockStore` reuses
+    // for refcount bookkeeping (see `db::block_store`), so its rows need excluding here
+    // -- they're never in `live_blocks` and would otherwise get physically deleted on
+    // every run, wiping out chunk6-1's refcounts out from under `prune_command`.
+    let to_delete: Vec<String> = conn
+        .read_all_block_hashes()?
+        .into_iter()
+        .filter(|hash| !is_block_refcount_key(hash) && !live_blocks.contains(hash))
+        .collect();
+
+    let bytes_reclaimed: usize = conn
+        .read_blocks(to_delete.clone())?
+        .iter()
+        .map(|record| record.data.len())
+        .sum();
+    let blocks_removed = to_delete.len();
+
+    conn.execute_in_transaction(|tx| Persistence::delete_blocks(tx, &to_delete))?;
+
+    Ok(GcReport {
+        blocks_removed,
+        bytes_reclaimed,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::NamedTempFile;
+
+    use crate::{
+        api::{prune_command::prune_branch, test_utils},
+        db::db_ops::{Persistence, DB},
+    };
+
+    use super::{gc, live_block_hashes};
+
+    #[test]
+    fn test_gc_then_prune_branch_keeps_blocks_shared_with_another_branch() {
+        let tmp_file = NamedTempFile::new().expect("Cannot create temp dir");
+        let tmp_path = tmp_file.path().to_str().expect("Cannot get temp file path");
+
+        test_utils::init_db_from_file(tmp_path, "my-cool-project", "data/fixtures/untitled.blend");
+        test_utils::new_branch(tmp_path, "dev");
+        test_utils::commit(tmp_path, "second checkpoint", "data/fixtures/untitled_2.blend");
+
+        let conn = Persistence::open(tmp_path).expect("Cannot open test DB");
+        let main_tip = conn
+            .read_branch_tip("main")
+            .expect("Cannot read main tip")
+            .expect("main has no tip");
+        let dev_tip = conn
+            .read_branch_tip("dev")
+            .expect("Cannot read dev tip")
+            .expect("dev has no tip");
+
+        let main_blocks = live_block_hashes(&conn, &main_tip).expect("Cannot read main blocks");
+        let dev_blocks = live_block_hashes(&conn, &dev_tip).expect("Cannot read dev blocks");
+        let shared_block = main_blocks
+            .intersection(&dev_blocks)
+            .next()
+            .cloned()
+            .expect("fixtures are expected to share at least one block");
+        drop(conn);
+
+        gc(tmp_path, None).expect("gc failed");
+        prune_branch(tmp_path, "dev").expect("prune_branch failed");
+
+        let conn = Persistence::open(tmp_path).expect("Cannot reopen test DB");
+        assert!(
+            conn.check_block_exists(&shared_block)
+                .expect("Cannot check block existence"),
+            "block shared with the surviving main branch must not be deleted"
+        );
+    }
+}
@@ -1,32 +1,61 @@
 use std::time::Instant;
 
 use crate::{
-    api::utils::get_file_size_str,
+    api::{
+        signing::{sign_commit_hash, signing_key_from_env},
+        utils::get_file_size_str,
+    },
     db::{
+        block_store::open_block_store,
         db_ops::{DBError, Persistence, DB},
+        migrations::CURRENT_SCHEMA_VERSION,
         structs::Commit,
     },
     measure_time,
 };
 
-use super::common::{blend_file_data_from_file, get_file_mod_time};
+use super::common::{
+    blend_file_data_from_file, get_file_mod_time, parse_blocks_and_pointers, resolve_encryption_key,
+};
 
 pub const INITIAL_COMMIT_HASH: &str = "initial";
 pub const MAIN_BRANCH_NAME: &str = "main";
 
-pub fn init_db(db_path: &str, project_id: &str, path_to_blend: &str) -> anyhow::Result<()> {
+pub fn init_db(
+    db_path: &str,
+    project_id: &str,
+    path_to_blend: &str,
+    passphrase: Option<&str>,
+) -> anyhow::Result<()> {
     let connect_command_timer = Instant::now();
-    let blend_data = blend_file_data_from_file(path_to_blend)
-        .map_err(|e| DBError::Error(format!("Error parsing blend file: {}", e)))?;
 
     let file_last_mod_time = get_file_mod_time(path_to_blend)?;
 
-    let mut db = Persistence::open(db_path)?;
+    // A passphrase protects the store at two layers: the SQLite file itself, opened
+    // here via SQLCipher, and (below) the block contents inside it, which stay
+    // encrypted even for a reader who somehow gets a decrypted copy of the file.
+    let mut db = match passphrase {
+        Some(passphrase) => Persistence::open_encrypted(db_path, passphrase)?,
+        None => Persistence::open(db_path)?,
+    };
+
+    let encryption_key = resolve_encryption_key(&mut db, passphrase)?;
+
+    let blend_data = blend_file_data_from_file(path_to_blend, encryption_key.as_ref())
+        .map_err(|e| DBError::Error(format!("Error parsing blend file: {}", e)))?;
 
     let name = db.read_name()?.unwrap_or("Anon".to_owned());
 
     let hash = blend_data.hash.clone();
 
+    let mut all_hashes_in_commit: Vec<String> = Vec::new();
+    for meta in parse_blocks_and_pointers(&blend_data.blocks_and_pointers_bytes) {
+        all_hashes_in_commit.extend(meta.chunk_hashes);
+        if let Some(dictionary_hash) = meta.dictionary_hash {
+            all_hashes_in_commit.push(dictionary_hash);
+        }
+    }
+
     db.execute_in_transaction(|tx| {
         Persistence::write_branch_tip(tx, MAIN_BRANCH_NAME, &blend_data.hash)?;
 
@@ -45,15 +74,29 @@ pub fn init_db(db_path: &str, project_id: &str, path_to_blend: &str) -> anyhow::
         Persistence::write_commit(tx, commit)
     })?;
 
+    // Block storage is pluggable (see `db::block_store`), so it's written through its
+    // own driver rather than inside the relational `execute_in_transaction` calls that
+    // handle commits, branches, and config.
+    measure_time!(format!("Writing blocks {:?}", path_to_blend), {
+        let block_store = open_block_store(db_path)?;
+        block_store.write_blocks(&blend_data.block_data)?;
+        block_store.increment_refcounts(&all_hashes_in_commit)?;
+    });
+
     db.execute_in_transaction(|tx| {
-        measure_time!(format!("Writing blocks {:?}", path_to_blend), {
-            Persistence::write_blocks(tx, &blend_data.block_data)?;
-        });
         Persistence::write_branch_tip(tx, MAIN_BRANCH_NAME, &hash)?;
         Persistence::write_current_commit_pointer(tx, &hash)?;
         Persistence::write_current_branch_name(tx, MAIN_BRANCH_NAME)?;
         Persistence::write_project_id(tx, project_id)?;
         Persistence::write_last_modifiction_time(tx, file_last_mod_time)?;
+        Persistence::write_schema_version(tx, CURRENT_SCHEMA_VERSION)?;
+
+        // Signing is opt-in: only runs when `COMMIT_SIGNING_SEED` is set.
+        if let Some(signing_key) = signing_key_from_env() {
+            let (author_pubkey, signature) = sign_commit_hash(&hash, &signing_key);
+            Persistence::write_commit_signature(tx, &hash, &author_pubkey, &signature)?;
+        }
+
         Ok(())
     })?;
 
@@ -83,6 +126,7 @@ mod test {
             tmp_path,
             "my amazing project",
             "data/fixtures/untitled.blend",
+            None,
         )
         .expect("Cannot init DB");
 
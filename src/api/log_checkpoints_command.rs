@@ -0,0 +1,64 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::db::db_ops::{Persistence, ShortCommitRecord, DB};
+
+/// Every checkpoint reachable from `branch`'s tip, in topological order: a commit is
+/// never emitted before any of its parents, with ties (a commit's siblings, or
+/// unrelated commits from a since-merged branch) broken by date, oldest first.
+/// `read_ancestors_of_commit` alone only guarantees a plain date ordering, which a
+/// merge commit with a slower clock on one side can put ahead of a parent it
+/// actually depends on.
+pub fn list_checkpoints(db_path: &str, branch: &str) -> anyhow::Result<Vec<ShortCommitRecord>> {
+    let conn = Persistence::open(db_path)?;
+
+    let tip = match conn.read_branch_tip(branch)? {
+        Some(tip) => tip,
+        None => return Ok(vec![]),
+    };
+
+    let mut ancestors = conn.read_ancestors_of_commit(&tip)?;
+    ancestors.reverse(); // oldest first, so ties below break in commit order
+
+    let known: HashSet<&str> = ancestors.iter().map(|c| c.hash.as_str()).collect();
+
+    let mut parents_of: HashMap<String, Vec<String>> = HashMap::new();
+    for commit in &ancestors {
+        let parents = conn
+            .read_parents_of_commit(&commit.hash)?
+            .into_iter()
+            .filter(|parent| known.contains(parent.as_str()))
+            .collect();
+        parents_of.insert(commit.hash.clone(), parents);
+    }
+
+    let mut emitted: HashSet<String> = HashSet::new();
+    let mut ordered: Vec<ShortCommitRecord> = Vec::with_capacity(ancestors.len());
+
+    while ordered.len() < ancestors.len() {
+        let mut made_progress = false;
+
+        for commit in &ancestors {
+            if emitted.contains(&commit.hash) {
+                continue;
+            }
+
+            let ready = parents_of[&commit.hash]
+                .iter()
+                .all(|parent| emitted.contains(parent));
+
+            if ready {
+                emitted.insert(commit.hash.clone());
+                ordered.push(commit.clone());
+                made_progress = true;
+            }
+        }
+
+        if !made_progress {
+            // Shouldn't happen -- `commit_parents` only ever points at commits that
+            // were already written -- but don't spin forever if it somehow does.
+            break;
+        }
+    }
+
+    Ok(ordered)
+}
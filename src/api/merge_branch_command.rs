@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+
+use crate::db::{
+    block_store::open_block_store,
+    db_ops::{DBError, Persistence, DB},
+    structs::Commit,
+};
+
+use super::common::{get_hash, parse_blocks_and_pointers, print_blocks_and_pointers, BlockMetadata};
+use crate::blend::utils::Either;
+
+/// A block that changed to a different hash on both sides of the merge relative to
+/// their common ancestor. The merge keeps `ours_hash` so the resulting checkpoint
+/// still restores; the caller is responsible for surfacing these for manual review.
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    pub block_address: u64,
+    pub base_hash: Option<String>,
+    pub ours_hash: String,
+    pub theirs_hash: String,
+}
+
+pub struct MergeBranchResult {
+    pub merged_commit_hash: String,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Output of [`three_way_merge_commits`]: the merged block layout plus its
+/// content-addressed hash, still unwritten, and whatever conflicts it found along
+/// the way. Kept separate from [`MergeBranchResult`] so a caller that hasn't
+/// committed yet (e.g. to check `check_commit_exists` first) isn't holding a result
+/// shaped as if it had.
+struct ThreeWayMerge {
+    blocks_and_pointers_bytes: Vec<u8>,
+    merged_commit_hash: String,
+    conflicts: Vec<MergeConflict>,
+}
+
+/// Every block hash `blocks_and_pointers` references -- the deduplicated chunk set
+/// plus any dictionary each block was compressed against -- so a merge commit's
+/// refcounts can be bumped the same way `create_new_checkpoint` bumps them for a
+/// freshly written commit (see `src/api/create_new_checkpoint_command.rs`).
+fn referenced_block_hashes(blocks_and_pointers: &[u8]) -> Vec<String> {
+    let mut hashes = Vec::new();
+    for meta in parse_blocks_and_pointers(blocks_and_pointers) {
+        hashes.extend(meta.chunk_hashes);
+        if let Some(dictionary_hash) = meta.dictionary_hash {
+            hashes.push(dictionary_hash);
+        }
+    }
+    hashes
+}
+
+fn address_key(address: &Either<u32, u64>) -> u64 {
+    match address {
+        Either::Left(value) => *value as u64,
+        Either::Right(value) => *value,
+    }
+}
+
+fn block_map(blocks: &[BlockMetadata]) -> HashMap<u64, &BlockMetadata> {
+    blocks
+        .iter()
+        .map(|block| (address_key(&block.original_mem_address), block))
+        .collect()
+}
+
+/// Walks `prev_commit_hash` back to the root, most recent first, from both tips and
+/// returns the first hash that appears in both chains -- the most recent common
+/// ancestor.
+fn lowest_common_ancestor(
+    conn: &Persistence,
+    ours_tip: &str,
+    theirs_tip: &str,
+) -> anyhow::Result<Option<String>> {
+    let ours_ancestors = conn.read_ancestors_of_commit(ours_tip)?;
+    let theirs_ancestors: HashSet<String> = conn
+        .read_ancestors_of_commit(theirs_tip)?
+        .into_iter()
+        .map(|short| short.hash)
+        .collect();
+
+    Ok(ours_ancestors
+        .into_iter()
+        .map(|short| short.hash)
+        .find(|hash| theirs_ancestors.contains(hash)))
+}
+
+/// Three-way merges the block sets of `ours_tip` and `theirs_tip` relative to their
+/// lowest common ancestor: a block changed on only one side is taken as-is, a block
+/// changed on both sides to the same hash is taken as-is, and a block changed on both
+/// sides to *different* hashes is a conflict -- `ours` wins so the merged checkpoint
+/// still restores, and the conflict is reported for manual review.
+fn three_way_merge_commits(
+    conn: &Persistence,
+    ours_tip: &str,
+    theirs_tip: &str,
+) -> anyhow::Result<ThreeWayMerge> {
+    let base_hash = lowest_common_ancestor(conn, ours_tip, theirs_tip)?.ok_or_else(|| {
+        DBError::Consistency("Branches to merge share no common ancestor".to_owned())
+    })?;
+
+    let ours_commit = conn
+        .read_commit(ours_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for ours tip".to_owned()))?;
+    let theirs_commit = conn
+        .read_commit(theirs_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for theirs tip".to_owned()))?;
+    let base_commit = conn
+        .read_commit(&base_hash)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for common ancestor".to_owned()))?;
+
+    let base_blocks = parse_blocks_and_pointers(&base_commit.blocks_and_pointers);
+    let ours_blocks = parse_blocks_and_pointers(&ours_commit.blocks_and_pointers);
+    let theirs_blocks = parse_blocks_and_pointers(&theirs_commit.blocks_and_pointers);
+
+    let base_map = block_map(&base_blocks);
+    let ours_map = block_map(&ours_blocks);
+    let theirs_map = block_map(&theirs_blocks);
+
+    let mut addresses: Vec<u64> = ours_map.keys().chain(theirs_map.keys()).copied().collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+
+    let mut merged_blocks: Vec<BlockMetadata> = Vec::new();
+    let mut conflicts: Vec<MergeConflict> = Vec::new();
+
+    for address in addresses {
+        let ours = ours_map.get(&address);
+        let theirs = theirs_map.get(&address);
+
+        let chosen = match (ours, theirs) {
+            (Some(ours), Some(theirs)) if ours.hash == theirs.hash => Some((*ours).clone()),
+            (Some(ours), Some(theirs)) => {
+                let base_hash = base_map.get(&address).map(|block| block.hash.clone());
+                if base_hash.as_deref() == Some(ours.hash.as_str()) {
+                    Some((*theirs).clone())
+                } else if base_hash.as_deref() == Some(theirs.hash.as_str()) {
+                    Some((*ours).clone())
+                } else {
+                    conflicts.push(MergeConflict {
+                        block_address: address,
+                        base_hash,
+                        ours_hash: ours.hash.clone(),
+                        theirs_hash: theirs.hash.clone(),
+                    });
+                    Some((*ours).clone())
+                }
+            }
+            (Some(ours), None) => Some((*ours).clone()),
+            (None, Some(theirs)) => Some((*theirs).clone()),
+            (None, None) => None,
+        };
+
+        if let Some(block) = chosen {
+            merged_blocks.push(block);
+        }
+    }
+
+    let blocks_and_pointers_bytes = print_blocks_and_pointers(merged_blocks);
+    let merged_commit_hash = get_hash(&blocks_and_pointers_bytes);
+
+    Ok(ThreeWayMerge {
+        blocks_and_pointers_bytes,
+        merged_commit_hash,
+        conflicts,
+    })
+}
+
+/// Merges `source_branch` into `target_branch` by walking each branch's commits back
+/// to their lowest common ancestor, then three-way merging the block sets (see
+/// [`three_way_merge_commits`]). The merged checkpoint is committed onto
+/// `target_branch` with `source_branch`'s tip recorded as its second parent.
+pub fn merge_branch(
+    db_path: &str,
+    source_branch: &str,
+    target_branch: &str,
+) -> anyhow::Result<MergeBranchResult> {
+    let mut conn = Persistence::open(db_path)?;
+
+    let ours_tip = conn.read_branch_tip(target_branch)?.ok_or_else(|| {
+        DBError::Error(format!("Branch {} does not exist", target_branch))
+    })?;
+    let theirs_tip = conn.read_branch_tip(source_branch)?.ok_or_else(|| {
+        DBError::Error(format!("Branch {} does not exist", source_branch))
+    })?;
+
+    if ours_tip == theirs_tip {
+        bail!(DBError::Error(
+            "Nothing to merge, branches point at the same checkpoint".to_owned(),
+        ));
+    }
+
+    let merge = three_way_merge_commits(&conn, &ours_tip, &theirs_tip)?;
+
+    if conn.check_commit_exists(&merge.merged_commit_hash)? {
+        // The merge produced a checkpoint identical to one we already have, e.g. a
+        // merge re-run after the branches were already reconciled.
+        return Ok(MergeBranchResult {
+            merged_commit_hash: merge.merged_commit_hash,
+            conflicts: merge.conflicts,
+        });
+    }
+
+    let ours_commit = conn
+        .read_commit(&ours_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for ours tip".to_owned()))?;
+    let theirs_commit = conn
+        .read_commit(&theirs_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for theirs tip".to_owned()))?;
+
+    let project_id = conn.read_project_id()?;
+    let author = conn.read_name()?.unwrap_or_default();
+    let date = ours_commit.date.max(theirs_commit.date);
+
+    // A merge can pull in blocks that only `theirs_tip`'s branch held a reference to.
+    // Bump their refcounts the same way `create_new_checkpoint` does for a freshly
+    // written commit, or pruning/deleting the branch that first introduced them would
+    // later drop the count to zero and physically delete blocks this merge commit
+    // still needs to restore.
+    let all_hashes_in_commit = referenced_block_hashes(&merge.blocks_and_pointers_bytes);
+    open_block_store(db_path)?.increment_refcounts(&all_hashes_in_commit)?;
+
+    let commit = Commit {
+        hash: merge.merged_commit_hash.clone(),
+        prev_commit_hash: ours_tip.clone(),
+        project_id,
+        branch: target_branch.to_string(),
+        message: format!("Merge branch '{}' into '{}'", source_branch, target_branch),
+        author,
+        date,
+        header: ours_commit.header,
+        blocks_and_pointers: merge.blocks_and_pointers_bytes,
+    };
+
+    conn.execute_in_transaction(|tx| {
+        Persistence::write_commit(tx, commit)?;
+        Persistence::write_commit_parents(
+            tx,
+            &merge.merged_commit_hash,
+            &[ours_tip.clone(), theirs_tip.clone()],
+        )?;
+        Persistence::write_merge_parent(tx, &merge.merged_commit_hash, &theirs_tip)?;
+        Persistence::write_branch_tip(tx, target_branch, &merge.merged_commit_hash)?;
+        Persistence::write_current_commit_pointer(tx, &merge.merged_commit_hash)
+    })?;
+
+    Ok(MergeBranchResult {
+        merged_commit_hash: merge.merged_commit_hash,
+        conflicts: merge.conflicts,
+    })
+}
+
+/// Generalizes [`merge_branch`] to arbitrary commit hashes rather than two branch
+/// tips resolved by name, so a merge checkpoint can be created from a detached HEAD
+/// (the situation `create_new_checkpoint` itself refuses: "Create a new branch to
+/// create a checkpoint") or between two commits that aren't the current tip of
+/// either branch. `commit_parents` records `ours_tip` and `theirs_tip` as parents 0
+/// and 1 of the resulting commit via [`DB::write_commit_parents`]; unlike
+/// `merge_branch`, no second-parents-only `merge_parents` row is written, since that
+/// table only ever supported exactly one extra parent.
+pub fn create_merge_checkpoint(
+    db_path: &str,
+    target_branch: &str,
+    ours_tip: &str,
+    theirs_tip: &str,
+    message: Option<String>,
+) -> anyhow::Result<MergeBranchResult> {
+    let mut conn = Persistence::open(db_path)?;
+
+    if ours_tip == theirs_tip {
+        bail!(DBError::Error(
+            "Nothing to merge, both parents point at the same checkpoint".to_owned(),
+        ));
+    }
+
+    let merge = three_way_merge_commits(&conn, ours_tip, theirs_tip)?;
+
+    if conn.check_commit_exists(&merge.merged_commit_hash)? {
+        return Ok(MergeBranchResult {
+            merged_commit_hash: merge.merged_commit_hash,
+            conflicts: merge.conflicts,
+        });
+    }
+
+    let ours_commit = conn
+        .read_commit(ours_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for ours tip".to_owned()))?;
+    let theirs_commit = conn
+        .read_commit(theirs_tip)?
+        .ok_or_else(|| DBError::Consistency("Missing commit for theirs tip".to_owned()))?;
+
+    let project_id = conn.read_project_id()?;
+    let author = conn.read_name()?.unwrap_or_default();
+    let date = ours_commit.date.max(theirs_commit.date);
+
+    // See the matching comment in `merge_branch`: this commit can reference blocks
+    // that only `theirs_tip` held a reference to, and their refcounts need bumping
+    // the same way a freshly written checkpoint's do.
+    let all_hashes_in_commit = referenced_block_hashes(&merge.blocks_and_pointers_bytes);
+    open_block_store(db_path)?.increment_refcounts(&all_hashes_in_commit)?;
+
+    let commit = Commit {
+        hash: merge.merged_commit_hash.clone(),
+        prev_commit_hash: ours_tip.to_string(),
+        project_id,
+        branch: target_branch.to_string(),
+        message: message
+            .unwrap_or_else(|| format!("Merge {} into {}", theirs_tip, target_branch)),
+        author,
+        date,
+        header: ours_commit.header,
+        blocks_and_pointers: merge.blocks_and_pointers_bytes,
+    };
+
+    conn.execute_in_transaction(|tx| {
+        Persistence::write_commit(tx, commit)?;
+        Persistence::write_commit_parents(
+            tx,
+            &merge.merged_commit_hash,
+            &[ours_tip.to_string(), theirs_tip.to_string()],
+        )?;
+        Persistence::write_branch_tip(tx, target_branch, &merge.merged_commit_hash)?;
+        Persistence::write_current_commit_pointer(tx, &merge.merged_commit_hash)
+    })?;
+
+    Ok(MergeBranchResult {
+        merged_commit_hash: merge.merged_commit_hash,
+        conflicts: merge.conflicts,
+    })
+}
@@ -0,0 +1,179 @@
+use std::fs;
+
+use rusqlite::OptionalExtension;
+
+use crate::db::{
+    db_ops::{Persistence, DB},
+    migrations::{CURRENT_SCHEMA_VERSION, MIGRATIONS},
+};
+
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub applied: Vec<String>,
+}
+
+/// Backs up the store to `<db_path>.bak-v{from_version}` before touching it -- a
+/// migration that fails partway through shouldn't leave the only copy of a user's
+/// timeline in a half-migrated state.
+fn backup(db_path: &str, from_version: i64) -> anyhow::Result<()> {
+    let backup_path = format!("{}.bak-v{}", db_path, from_version);
+    fs::copy(db_path, backup_path)?;
+    Ok(())
+}
+
+/// Reads `schema_version` straight off the file, without going through
+/// `Persistence::open` -- which migrates the store as a side effect of opening it
+/// (see [`crate::db::db_ops::Persistence::run_pending_migrations`]) and would make it
+/// impossible to tell what version the file was *before* that happened. Any read
+/// failure (a nonexistent file, or a store old enough to predate the `config` table
+/// entirely) is treated as version `0`, same as `DB::read_schema_version`'s own
+/// default.
+fn read_on_disk_schema_version(db_path: &str) -> i64 {
+    rusqlite::Connection::open(db_path)
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT value FROM config WHERE key = 'SCHEMA_VERSION'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+        })
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Reports on (and backs up before) the migration [`Persistence::open`] already
+/// performs automatically. Detects the schema version the store is at *before*
+/// opening it, backs the file up to `<db_path>.bak-v{from_version}` if anything is
+/// pending, then opens it -- which is what actually runs the migrations, each inside
+/// its own transaction that also stamps the new version, so a crash partway through
+/// leaves the store at the last successfully-applied version rather than silently
+/// corrupted. A store that's already current is a no-op and skips the backup step
+/// entirely.
+pub fn migrate(db_path: &str) -> anyhow::Result<MigrateReport> {
+    let from_version = read_on_disk_schema_version(db_path);
+
+    let pending: Vec<&crate::db::migrations::Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from_version)
+        .collect();
+
+    if pending.is_empty() {
+        return Ok(MigrateReport {
+            from_version,
+            to_version: from_version,
+            applied: vec![],
+        });
+    }
+
+    backup(db_path, from_version)?;
+
+    let applied = pending
+        .iter()
+        .map(|m| m.description.to_owned())
+        .collect();
+
+    Persistence::open(db_path)?;
+
+    Ok(MigrateReport {
+        from_version,
+        to_version: CURRENT_SCHEMA_VERSION,
+        applied,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::NamedTempFile;
+
+    use crate::db::db_ops::{Persistence, DB};
+
+    use super::migrate;
+
+    #[test]
+    fn test_migrate_fresh_store_reaches_current_version() {
+        let tmp_file = NamedTempFile::new().expect("Cannot create temp dir");
+        let tmp_path = tmp_file.path().to_str().expect("Cannot get temp file path");
+
+        // `migrate` detects the version before `Persistence::open` runs, so calling it
+        // against a file that doesn't exist yet still reports the full climb to
+        // current, even though `open` (inside `migrate`) is what actually performs it.
+        let report = migrate(tmp_path).expect("Cannot migrate db");
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, super::CURRENT_SCHEMA_VERSION);
+        assert_eq!(report.applied.len(), 4);
+
+        let second_report = migrate(tmp_path).expect("Cannot migrate db again");
+        assert!(second_report.applied.is_empty());
+    }
+
+    #[test]
+    fn test_open_migrates_old_format_fixture() {
+        let tmp_file = NamedTempFile::new().expect("Cannot create temp dir");
+        let tmp_path = tmp_file.path().to_str().expect("Cannot get temp file path");
+
+        // Hand-build a store in the shape `Persistence::open` produced before the
+        // `merge_parents` table and `schema_version` config row existed, so opening
+        // it through today's code has to migrate it in place rather than erroring.
+        {
+            let conn = rusqlite::Connection::open(tmp_path).expect("Cannot create fixture db");
+            conn.execute(
+                "CREATE TABLE commits (
+                    hash TEXT PRIMARY KEY,
+                    prev_commit_hash TEXT,
+                    project_id TEXT,
+                    branch TEXT,
+                    message TEXT,
+                    author TEXT,
+                    date INTEGER,
+                    header BLOB,
+                    blocks_and_pointers BLOB
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute("CREATE TABLE branches (name TEXT PRIMARY KEY, tip TEXT)", [])
+                .unwrap();
+            conn.execute("CREATE TABLE blocks (key TEXT PRIMARY KEY, value BLOB)", [])
+                .unwrap();
+            conn.execute("CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT)", [])
+                .unwrap();
+
+            conn.execute(
+                "INSERT INTO commits (hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers)
+                 VALUES ('old-hash', 'initial', 'old-project', 'main', 'Old checkpoint', 'Anon', 0, x'', x'')",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO branches (name, tip) VALUES ('main', 'old-hash')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Persistence::open(tmp_path).expect("Cannot open old-format fixture db");
+
+        let commit = db
+            .read_commit("old-hash")
+            .expect("Cannot read commit from migrated db")
+            .expect("Commit missing after migration");
+        assert_eq!(commit.branch, "main");
+        assert_eq!(commit.message, "Old checkpoint");
+
+        let tip = db
+            .read_branch_tip("main")
+            .expect("Cannot read branch tip from migrated db");
+        assert_eq!(tip, Some("old-hash".to_owned()));
+
+        assert_eq!(
+            db.read_schema_version()
+                .expect("Cannot read schema version"),
+            super::CURRENT_SCHEMA_VERSION
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::db::{
+    block_store::open_block_store,
+    db_ops::{DBError, Persistence, DB},
+    structs::Commit,
+};
+
+use super::common::parse_blocks_and_pointers;
+
+fn block_hashes_of_commit(commit: &Commit) -> Vec<String> {
+    let mut hashes = Vec::new();
+    for meta in parse_blocks_and_pointers(&commit.blocks_and_pointers) {
+        hashes.extend(meta.chunk_hashes);
+        if let Some(dictionary_hash) = meta.dictionary_hash {
+            hashes.push(dictionary_hash);
+        }
+    }
+    hashes
+}
+
+/// Removes a single commit and drops its block store refcounts by one each,
+/// physically deleting any block whose count reaches zero. Unlike [`super::gc_command::gc`],
+/// this doesn't walk the whole DAG to rebuild a live set from scratch -- it trusts the
+/// refcounts accumulated at write time to be the deletion authority, so it stays cheap
+/// even for a project with a very long history.
+///
+/// The relational delete runs before the refcount decrement, not after: `BlockStore`
+/// is a separate store from `Persistence` (possibly a different engine entirely, see
+/// `db::block_store`), so the two writes can't share one transaction. Decrementing
+/// first and then failing/crashing before the commit row is gone would leave that row
+/// pointing at blocks that no longer exist; deleting first and failing before the
+/// decrement instead just leaves some blocks over-counted, which a later
+/// `super::gc_command::gc` sweep reconciles.
+pub fn delete_commit(db_path: &str, commit_hash: &str) -> anyhow::Result<()> {
+    let mut conn = Persistence::open(db_path)?;
+
+    let commit = conn.read_commit(commit_hash)?.ok_or_else(|| {
+        anyhow::anyhow!(DBError::Error(format!(
+            "No commit with hash {:?} found",
+            commit_hash
+        )))
+    })?;
+
+    conn.execute_in_transaction(|tx| Persistence::delete_commit(tx, commit_hash))?;
+
+    open_block_store(db_path)?.decrement_refcounts(&block_hashes_of_commit(&commit))
+}
+
+/// Removes every commit stored on `branch_name` along with the branch itself,
+/// decrementing block store refcounts for each commit once the branch is gone. See
+/// [`delete_commit`]'s doc comment for why the relational delete runs first.
+pub fn prune_branch(db_path: &str, branch_name: &str) -> anyhow::Result<()> {
+    let mut conn = Persistence::open(db_path)?;
+
+    let commits = conn.read_commits_for_branch(branch_name)?;
+
+    conn.execute_in_transaction(|tx| Persistence::delete_branch_with_commits(tx, branch_name))?;
+
+    let block_store = open_block_store(db_path)?;
+    for commit in &commits {
+        block_store.decrement_refcounts(&block_hashes_of_commit(commit))?;
+    }
+
+    Ok(())
+}
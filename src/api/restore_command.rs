@@ -1,26 +1,47 @@
-use std::{io::Write, iter::zip, time::Instant};
+use std::{collections::HashMap, time::Instant};
 
 use anyhow::{bail, Context};
-use flate2::write::GzDecoder;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    api::common::parse_blocks_and_pointers,
+    api::{
+        common::{get_hash, parse_blocks_and_pointers},
+        signing::verify_stored_signature,
+    },
     blend::{
         parse_print_blend::{
-            parse_block_manual, parse_header_manual, print_blend, BlendFileWithPointerData,
-            BlockContentWithPointers,
+            parse_block_manual, parse_header_manual, print_block_manual, print_blend,
+            BlendFileWithPointerData, BlockContentWithPointers,
         },
-        utils::to_file_transactional,
+        utils::{decompress_block, decompress_block_with_dict, decrypt_block, to_file_transactional},
+    },
+    db::{
+        block_cache::BLOCK_CACHE,
+        block_store::open_block_store,
+        db_ops::{DBError, Persistence, DB},
     },
-    db::db_ops::{DBError, Persistence, DB},
     measure_time,
 };
 
-pub fn restore_checkpoint(file_path: &str, db_path: &str, hash: &str) -> anyhow::Result<()> {
+pub fn restore_checkpoint(
+    file_path: &str,
+    db_path: &str,
+    hash: &str,
+    encryption_key: Option<&[u8; 32]>,
+) -> anyhow::Result<()> {
     let restore_command_timer = Instant::now();
 
+    // Decryption, when the store has blocks encrypted at rest, always runs before
+    // decompression.
+    let maybe_decrypt = |data: &[u8]| -> anyhow::Result<Vec<u8>> {
+        match encryption_key {
+            Some(key) => decrypt_block(data, key).map_err(anyhow::Error::new),
+            None => Ok(data.to_vec()),
+        }
+    };
+
     let mut conn = Persistence::open(db_path)?;
+    let block_store = open_block_store(db_path)?;
 
     let commit = measure_time!(format!("Reading commit {:?}", hash), {
         conn.read_commit(hash)
@@ -34,6 +55,22 @@ pub fn restore_checkpoint(file_path: &str, db_path: &str, hash: &str) -> anyhow:
     }
     let commit = commit.unwrap();
 
+    // Every block is content-addressed, so a bit-for-bit reproduction of
+    // `blocks_and_pointers` must hash back to `commit.hash` -- a mismatch means this
+    // row was corrupted (or tampered with) after it was written, and checking out
+    // from it would silently produce a broken `.blend` file.
+    if get_hash(&commit.blocks_and_pointers) != commit.hash {
+        bail!(DBError::Consistency(format!(
+            "Commit {:?} is corrupted: blocks_and_pointers does not match its hash",
+            commit.hash
+        )));
+    }
+
+    // Opt-in: passes straight through for unsigned commits unless
+    // `REQUIRE_COMMIT_SIGNATURES` is set, and rejects the restore if a stored
+    // signature doesn't verify against the commit hash.
+    verify_stored_signature(&conn, &commit.hash)?;
+
     let block_meta = measure_time!(format!("Reading blocks {:?}", hash), {
         parse_blocks_and_pointers(&commit.blocks_and_pointers)
     });
@@ -41,37 +78,106 @@ pub fn restore_checkpoint(file_path: &str, db_path: &str, hash: &str) -> anyhow:
     let header_data = commit.header;
     let (header, _) = parse_header_manual(&header_data).unwrap();
 
+    // At most one shared zstd dictionary per checkpoint (see `BlockMetadata::dictionary_hash`).
+    let dictionary_hash = block_meta.iter().find_map(|b| b.dictionary_hash.clone());
+    let dictionary: Option<Vec<u8>> = dictionary_hash
+        .map(|hash| -> anyhow::Result<Vec<u8>> {
+            let records = block_store
+                .read_blocks(vec![hash])
+                .context("Cannot read dictionary block")?;
+            let decrypted = maybe_decrypt(&records[0].data)?;
+            decompress_block(&decrypted).context("Cannot decompress dictionary block")
+        })
+        .transpose()?;
+
     let blocks: Vec<BlockContentWithPointers> =
         measure_time!(format!("Decompressing blocks {:?}", hash), {
-            let block_hashes = block_meta.iter().map(|b| b.hash.clone()).collect();
+            let all_chunk_hashes: Vec<String> = block_meta
+                .iter()
+                .flat_map(|b| b.chunk_hashes.iter().cloned())
+                .collect();
 
-            let blocks_minus_pointers: Vec<Vec<u8>> = conn
-                .read_blocks(block_hashes)
+            let chunk_results: Vec<anyhow::Result<(String, Vec<u8>)>> = block_store
+                .read_blocks(all_chunk_hashes)
                 .context("Cannot read block hashes")?
                 .par_iter()
-                .map(|record| {
-                    let mut writer = Vec::new();
-                    let mut deflater = GzDecoder::new(writer);
-                    deflater.write_all(&record.data).unwrap();
-                    writer = deflater.finish().unwrap();
-                    writer
+                .map(|record| -> anyhow::Result<(String, Vec<u8>)> {
+                    // Blocks are immutable and content-addressed, so a cache hit never
+                    // needs invalidation -- only a miss has to decrypt and decompress.
+                    if let Some(cached) = BLOCK_CACHE.get(&record.hash) {
+                        return Ok((record.hash.clone(), cached));
+                    }
+
+                    let decrypted = maybe_decrypt(&record.data)?;
+                    let data = decompress_block_with_dict(&decrypted, dictionary.as_deref())
+                        .map_err(|e| {
+                            anyhow::anyhow!("Cannot decompress block {:?}: {:?}", record.hash, e)
+                        })?;
+
+                    if get_hash(&data) != record.hash {
+                        bail!(DBError::Consistency(format!(
+                            "Block {:?} is corrupted: decompressed content does not match its hash",
+                            record.hash
+                        )));
+                    }
+
+                    BLOCK_CACHE.insert(record.hash.clone(), data.clone());
+
+                    Ok((record.hash.clone(), data))
                 })
                 .collect();
 
-            zip(block_meta, blocks_minus_pointers)
-                .map(|(meta, data)| {
+            let chunks_by_hash: HashMap<String, Vec<u8>> = chunk_results
+                .into_iter()
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .into_iter()
+                .collect();
+
+            block_meta
+                .into_iter()
+                .map(|meta| -> anyhow::Result<BlockContentWithPointers> {
+                    let mut data = Vec::new();
+                    for chunk_hash in &meta.chunk_hashes {
+                        let chunk = chunks_by_hash
+                            .get(chunk_hash)
+                            .expect("missing chunk referenced by block metadata");
+                        data.extend_from_slice(chunk);
+                    }
+
+                    let (simple_block, _) =
+                        parse_block_manual(&data, header.pointer_size, header.endianness).unwrap();
+
+                    // `meta.hash` was computed over the re-serialized block, not over
+                    // `data` directly (see `blend_file_data_from_file`), so the
+                    // comparable bytes have to be re-serialized the same way here.
+                    // `print_block_manual` consumes its block by value, so re-parse
+                    // for the owned copy handed on below rather than requiring
+                    // `SimpleBlock: Clone`.
+                    let mut reserialized = Vec::new();
+                    print_block_manual(simple_block, header.endianness, &mut reserialized);
+                    if get_hash(&reserialized) != meta.hash {
+                        bail!(DBError::Consistency(format!(
+                            "Block {:?} is corrupted: reassembled block does not match its hash",
+                            meta.hash
+                        )));
+                    }
+
                     let (simple_block, _) =
                         parse_block_manual(&data, header.pointer_size, header.endianness).unwrap();
 
-                    BlockContentWithPointers {
+                    Ok(BlockContentWithPointers {
                         simple_block,
                         original_mem_address: meta.original_mem_address,
                         pointers: meta.pointers,
-                    }
+                    })
                 })
-                .collect()
+                .collect::<anyhow::Result<Vec<_>>>()?
         });
 
+    if let Some(hit_rate) = BLOCK_CACHE.hit_rate() {
+        println!("Block cache hit rate: {:.1}%", hit_rate * 100.0);
+    }
+
     let mut out: Vec<u8> = vec![];
     print_blend(BlendFileWithPointerData { header, blocks }, &mut out);
 
@@ -137,6 +243,7 @@ mod test {
             &tmp_blend_path,
             tmp_path,
             "94ab91e7ea864efd6cc228472d47d2a1ca648682ff25cbcb79a9d7a286811fb61d75bee6964aaeec2850f881f8b924dc88b626af405d0ffe813596c4f5033f84",
+            None,
         )
         .expect("Cannot restore checkpoint");
 
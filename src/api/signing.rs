@@ -0,0 +1,91 @@
+use std::env;
+
+use anyhow::bail;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::db::db_ops::{DBError, DB};
+
+const SIGNING_SEED_ENV_VAR: &str = "COMMIT_SIGNING_SEED";
+const REQUIRE_SIGNATURES_ENV_VAR: &str = "REQUIRE_COMMIT_SIGNATURES";
+
+/// Derives the author signing key from `COMMIT_SIGNING_SEED` (an arbitrary passphrase,
+/// hashed down to an ed25519 seed) if set. Signing is opt-in -- a project that never
+/// sets this keeps writing unsigned commits exactly as before.
+pub fn signing_key_from_env() -> Option<SigningKey> {
+    let seed_phrase = env::var(SIGNING_SEED_ENV_VAR).ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(seed_phrase.as_bytes());
+    let seed: [u8; 32] = hasher.finalize().into();
+    Some(SigningKey::from_bytes(&seed))
+}
+
+/// `true` if `REQUIRE_COMMIT_SIGNATURES` is set to a truthy value -- when strict,
+/// [`verify_stored_signature`] rejects a commit with no stored signature instead of
+/// silently accepting it.
+fn signatures_required() -> bool {
+    env::var(REQUIRE_SIGNATURES_ENV_VAR)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Signs a commit's content hash with `signing_key`, returning `(author_pubkey,
+/// signature)` to be stored alongside the commit in the `commit_signatures` table --
+/// kept out of the `commits` table itself since signing is optional and bolting two
+/// new columns onto every commit row isn't worth it for a feature most projects won't
+/// use.
+pub fn sign_commit_hash(commit_hash: &str, signing_key: &SigningKey) -> (Vec<u8>, Vec<u8>) {
+    let signature: Signature = signing_key.sign(commit_hash.as_bytes());
+    (
+        signing_key.verifying_key().to_bytes().to_vec(),
+        signature.to_bytes().to_vec(),
+    )
+}
+
+/// Verifies `signature` over `commit_hash` against `author_pubkey`.
+fn verify_commit_hash(
+    commit_hash: &str,
+    author_pubkey: &[u8],
+    signature: &[u8],
+) -> Result<(), DBError> {
+    let pubkey_bytes: [u8; 32] = author_pubkey.try_into().map_err(|_| {
+        DBError::Consistency(format!("commit {commit_hash} has a malformed public key"))
+    })?;
+    let verifying_key = VerifyingKey::from_bytes(&pubkey_bytes).map_err(|_| {
+        DBError::Consistency(format!("commit {commit_hash} has an invalid public key"))
+    })?;
+
+    let signature_bytes: [u8; 64] = signature.try_into().map_err(|_| {
+        DBError::Consistency(format!("commit {commit_hash} has a malformed signature"))
+    })?;
+    let signature = Signature::from_bytes(&signature_bytes);
+
+    verifying_key
+        .verify(commit_hash.as_bytes(), &signature)
+        .map_err(|_| {
+            DBError::Consistency(format!(
+                "commit {commit_hash} failed signature verification"
+            ))
+        })
+}
+
+/// Looks up and verifies `commit_hash`'s stored signature, if any. An unsigned commit
+/// passes unless [`signatures_required`] is strict -- signing is opt-in, so unsigned
+/// commits written before this feature existed (or received from a peer who doesn't
+/// sign) must keep restoring normally by default.
+pub fn verify_stored_signature<T: DB>(conn: &T, commit_hash: &str) -> anyhow::Result<()> {
+    match conn.read_commit_signature(commit_hash)? {
+        Some((author_pubkey, signature)) => {
+            verify_commit_hash(commit_hash, &author_pubkey, &signature)?;
+            Ok(())
+        }
+        None => {
+            if signatures_required() {
+                bail!(DBError::Consistency(format!(
+                    "commit {commit_hash} is unsigned but signatures are required"
+                )));
+            }
+            Ok(())
+        }
+    }
+}
@@ -2,9 +2,14 @@ use anyhow::bail;
 
 use crate::db::db_ops::{DBError, Persistence, DB};
 
-use super::restore_command::restore_checkpoint;
-
-pub fn switch_branches(db_path: &str, branch_name: &str, file_path: &str) -> anyhow::Result<()> {
+use super::{common::resolve_encryption_key, restore_command::restore_checkpoint};
+
+pub fn switch_branches(
+    db_path: &str,
+    branch_name: &str,
+    file_path: &str,
+    passphrase: Option<&str>,
+) -> anyhow::Result<()> {
     let hash = {
         let mut db = Persistence::open(db_path)?;
 
@@ -26,7 +31,10 @@ pub fn switch_branches(db_path: &str, branch_name: &str, file_path: &str) -> any
         hash
     };
 
-    restore_checkpoint(file_path, db_path, &hash)
+    let mut db = Persistence::open(db_path)?;
+    let encryption_key = resolve_encryption_key(&mut db, passphrase)?;
+
+    restore_checkpoint(file_path, db_path, &hash, encryption_key.as_ref())
 }
 
 #[cfg(test)]
@@ -49,7 +57,7 @@ mod test {
 
         test_utils::init_db_from_file(tmp_path, "my-cool-project", "data/fixtures/untitled.blend");
 
-        let res = switch_branches(tmp_path, "unknown", "void.blend");
+        let res = switch_branches(tmp_path, "unknown", "void.blend", None);
         assert!(res.is_err());
 
         let db = Persistence::open(tmp_path).expect("Cannot open test DB");
@@ -103,6 +111,7 @@ mod test {
             tmp_path,
             MAIN_BRANCH_NAME,
             tmp_blend_path.path().to_str().unwrap(),
+            None,
         )
         .expect("Cannot switch branches");
 
@@ -2,14 +2,14 @@
 pub fn init_db_from_file(db_path: &str, project_id: &str, blend_file_path: &str) {
     use super::init_command::init_db;
 
-    init_db(db_path, project_id, blend_file_path).expect("Cannot init DB")
+    init_db(db_path, project_id, blend_file_path, None).expect("Cannot init DB")
 }
 
 #[cfg(test)]
 pub fn commit(db_path: &str, message: &str, blend_path: &str) {
     use super::create_new_checkpoint_command::create_new_checkpoint;
 
-    create_new_checkpoint(blend_path, db_path, Some(message.to_owned()))
+    create_new_checkpoint(blend_path, db_path, Some(message.to_owned()), None)
         .expect("Cannot create new commit")
 }
 
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+
+use crate::{
+    api::common::{get_hash, parse_blocks_and_pointers},
+    blend::utils::{decompress_block, decompress_block_with_dict, decrypt_block, BlockCodec},
+    db::db_ops::{Persistence, DB},
+};
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Blocks referenced by some commit's metadata but absent from the store entirely.
+    pub missing: Vec<String>,
+    /// Blocks present in the store whose decompressed (and decrypted, if applicable)
+    /// content no longer hashes to the key it's stored under -- silent corruption.
+    pub corrupted: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+/// Walks every commit reachable from every branch tip and checks that each block it
+/// references is present and still hashes to the key it's stored under. Read-only:
+/// use [`repair_block_from_source`] to fix what this finds. `encryption_key` must
+/// match whatever the store was written with, or every block will report corrupted.
+pub fn verify_store(
+    db_path: &str,
+    encryption_key: Option<&[u8; 32]>,
+) -> anyhow::Result<VerifyReport> {
+    let conn = Persistence::open(db_path)?;
+
+    let mut report = VerifyReport::default();
+    let mut checked: HashSet<String> = HashSet::new();
+
+    for branch in conn.read_all_branches()? {
+        let tip = match conn.read_branch_tip(&branch)? {
+            Some(tip) => tip,
+            None => continue,
+        };
+
+        for short in conn.read_ancestors_of_commit(&tip)? {
+            let commit = match conn.read_commit(&short.hash)? {
+                Some(commit) => commit,
+                None => continue,
+            };
+
+            let block_meta = parse_blocks_and_pointers(&commit.blocks_and_pointers);
+
+            let dictionary_hash = block_meta.iter().find_map(|b| b.dictionary_hash.clone());
+            let dictionary: Option<Vec<u8>> = match dictionary_hash {
+                Some(hash) if checked.insert(hash.clone()) => {
+                    resolve_dictionary(&conn, &hash, encryption_key, &mut report)?
+                }
+                _ => None,
+            };
+
+            for meta in &block_meta {
+                for chunk_hash in &meta.chunk_hashes {
+                    if !checked.insert(chunk_hash.clone()) {
+                        continue;
+                    }
+                    verify_one_block(
+                        &conn,
+                        chunk_hash,
+                        dictionary.as_deref(),
+                        encryption_key,
+                        &mut report,
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn resolve_dictionary(
+    conn: &Persistence,
+    dictionary_hash: &str,
+    encryption_key: Option<&[u8; 32]>,
+    report: &mut VerifyReport,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    if !conn.check_block_exists(dictionary_hash)? {
+        report.missing.push(dictionary_hash.to_string());
+        return Ok(None);
+    }
+
+    let record = conn
+        .read_blocks(vec![dictionary_hash.to_string()])?
+        .into_iter()
+        .next()
+        .expect("just checked this block exists");
+
+    let compressed = match decrypt_or_pass_through(&record.data, encryption_key) {
+        Some(data) => data,
+        None => {
+            report.corrupted.push(dictionary_hash.to_string());
+            return Ok(None);
+        }
+    };
+
+    match decompress_block(&compressed) {
+        Ok(dict) if get_hash(&dict) == dictionary_hash => Ok(Some(dict)),
+        _ => {
+            report.corrupted.push(dictionary_hash.to_string());
+            Ok(None)
+        }
+    }
+}
+
+fn verify_one_block(
+    conn: &Persistence,
+    hash: &str,
+    dictionary: Option<&[u8]>,
+    encryption_key: Option<&[u8; 32]>,
+    report: &mut VerifyReport,
+) -> anyhow::Result<()> {
+    if !conn.check_block_exists(hash)? {
+        report.missing.push(hash.to_string());
+        return Ok(());
+    }
+
+    let record = conn
+        .read_blocks(vec![hash.to_string()])?
+        .into_iter()
+        .next()
+        .expect("just checked this block exists");
+
+    let compressed = match decrypt_or_pass_through(&record.data, encryption_key) {
+        Some(data) => data,
+        None => {
+            report.corrupted.push(hash.to_string());
+            return Ok(());
+        }
+    };
+
+    let decompressed = match decompress_block_with_dict(&compressed, dictionary) {
+        Ok(data) => data,
+        Err(_) => {
+            report.corrupted.push(hash.to_string());
+            return Ok(());
+        }
+    };
+
+    if get_hash(&decompressed) != hash {
+        report.corrupted.push(hash.to_string());
+    }
+
+    Ok(())
+}
+
+fn decrypt_or_pass_through(data: &[u8], encryption_key: Option<&[u8; 32]>) -> Option<Vec<u8>> {
+    match encryption_key {
+        Some(key) => decrypt_block(data, key).ok(),
+        None => Some(data.to_vec()),
+    }
+}
+
+/// Re-writes a single corrupt or missing block by copying its raw (still compressed
+/// and, if applicable, encrypted) bytes verbatim from another store known to hold a
+/// healthy copy under the same hash -- e.g. another branch's checkpoint DB or a
+/// backup. Both stores must use the same encryption settings, since the bytes are
+/// copied as-is rather than re-derived.
+pub fn repair_block_from_source(
+    dest_db_path: &str,
+    source_db_path: &str,
+    hash: &str,
+) -> anyhow::Result<()> {
+    let source_conn = Persistence::open(source_db_path)?;
+    let record = source_conn
+        .read_blocks(vec![hash.to_string()])
+        .context("Healthy source does not have this block either")?
+        .into_iter()
+        .next()
+        .context("Healthy source does not have this block either")?;
+
+    let mut dest_conn = Persistence::open(dest_db_path)?;
+    dest_conn.execute_in_transaction(|tx| Persistence::overwrite_block(tx, &record))
+}
+
+#[allow(dead_code)]
+fn is_dictionary_dependent(codec_tag: u8) -> bool {
+    codec_tag == BlockCodec::ZstdDict as u8
+}
@@ -1,6 +1,10 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{Cursor, Error, ErrorKind, Read, Write};
@@ -45,6 +49,410 @@ pub fn from_file(path: &str) -> Result<Vec<u8>, Error> {
     Ok(data)
 }
 
+/// Codec used to compress a single stored chunk/block. Persisted as a one-byte tag
+/// prefix on the stored bytes so a reader can pick the right decoder without
+/// consulting any side table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum BlockCodec {
+    Stored = 0,
+    Gzip = 1,
+    Zstd = 2,
+    /// Zstd with a shared, externally-supplied dictionary (see [`train_dictionary`]).
+    /// The dictionary itself is not part of the tagged bytes — the caller must look
+    /// it up (e.g. via `BlockMetadata::dictionary_hash`) and pass it to
+    /// [`decompress_block_with_dict`].
+    ZstdDict = 3,
+}
+
+impl BlockCodec {
+    fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(BlockCodec::Stored),
+            1 => Ok(BlockCodec::Gzip),
+            2 => Ok(BlockCodec::Zstd),
+            3 => Ok(BlockCodec::ZstdDict),
+            other => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown block codec tag: {other}"),
+            )),
+        }
+    }
+}
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compresses `data` with zstd, falling back to "stored" (no compression) when the
+/// compressed form would be larger than the input — a common case for blocks that
+/// already hold compressed image/texture data. The returned bytes are
+/// self-describing: a one-byte codec tag followed by the payload.
+pub fn compress_block(data: &[u8]) -> Vec<u8> {
+    let compressed = zstd::encode_all(Cursor::new(data), ZSTD_LEVEL).unwrap_or_else(|_| data.to_vec());
+
+    if compressed.len() < data.len() {
+        let mut tagged = Vec::with_capacity(compressed.len() + 1);
+        tagged.push(BlockCodec::Zstd as u8);
+        tagged.extend(compressed);
+        tagged
+    } else {
+        let mut tagged = Vec::with_capacity(data.len() + 1);
+        tagged.push(BlockCodec::Stored as u8);
+        tagged.extend_from_slice(data);
+        tagged
+    }
+}
+
+/// Reverses [`compress_block`], dispatching on the leading codec tag. Also accepts
+/// bare gzip payloads with no tag byte so older stores keep opening.
+pub fn decompress_block(tagged: &[u8]) -> Result<Vec<u8>, Error> {
+    let (tag, payload) = tagged
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Empty block payload"))?;
+
+    match BlockCodec::from_tag(*tag) {
+        Ok(BlockCodec::Stored) => Ok(payload.to_vec()),
+        Ok(BlockCodec::Zstd) => decode_zstd(&payload.to_vec()),
+        Ok(BlockCodec::Gzip) => decode_gzip(payload),
+        Ok(BlockCodec::ZstdDict) => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Block was compressed with a dictionary; use decompress_block_with_dict",
+        )),
+        Err(_) => decode_gzip(tagged),
+    }
+}
+
+// Largest dictionary `train_dictionary` produces, and the fewest samples it will
+// train one from.
+const DICTIONARY_MAX_SIZE: usize = 64 * 1024;
+const DICTIONARY_MIN_SAMPLES: usize = 8;
+
+/// Trains a zstd dictionary over `samples` (e.g. a checkpoint's content-defined
+/// chunks), so many small, structurally similar blocks can share one dictionary
+/// instead of each compressing independently. Returns `None` when there are too few
+/// samples to train a useful dictionary, or when training fails for any reason —
+/// callers should fall back to dictionary-less compression in that case.
+pub fn train_dictionary(samples: &[Vec<u8>]) -> Option<Vec<u8>> {
+    if samples.len() < DICTIONARY_MIN_SAMPLES {
+        return None;
+    }
+
+    zstd::dict::from_samples(samples, DICTIONARY_MAX_SIZE).ok()
+}
+
+/// Compresses `data` against a pre-trained dictionary (see [`train_dictionary`]).
+/// Falls back to the dictionary-less [`compress_block`] encoding if dictionary
+/// compression fails or does not help.
+pub fn compress_block_with_dict(data: &[u8], dict: &[u8]) -> Vec<u8> {
+    let with_dict = (|| -> Result<Vec<u8>, Error> {
+        let mut encoder = zstd::stream::write::Encoder::with_dictionary(Vec::new(), 0, dict)?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    })();
+
+    match with_dict {
+        Ok(compressed) if compressed.len() < data.len() => {
+            let mut tagged = Vec::with_capacity(compressed.len() + 1);
+            tagged.push(BlockCodec::ZstdDict as u8);
+            tagged.extend(compressed);
+            tagged
+        }
+        _ => compress_block(data),
+    }
+}
+
+/// Reverses [`compress_block_with_dict`]. `dict` must be the same dictionary bytes
+/// the block was compressed with; it is only consulted for the [`BlockCodec::ZstdDict`]
+/// tag, so this is safe to call for any block produced by either `compress_block` or
+/// `compress_block_with_dict`.
+pub fn decompress_block_with_dict(tagged: &[u8], dict: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let (tag, payload) = tagged
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Empty block payload"))?;
+
+    if *tag == BlockCodec::ZstdDict as u8 {
+        let dict = dict.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "Block was compressed with a dictionary, but none was supplied",
+            )
+        })?;
+        let mut decoder = zstd::stream::read::Decoder::with_dictionary(payload, dict)?;
+        let mut data = Vec::new();
+        decoder.read_to_end(&mut data)?;
+        return Ok(data);
+    }
+
+    decompress_block(tagged)
+}
+
+/// Offset and length of one block's frame within a [`write_framed_blocks`] buffer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockFrameIndexEntry {
+    pub hash: String,
+    pub offset: u64,
+    pub length: u32,
+}
+
+/// Packs already-compressed (and, if applicable, encrypted) blocks into one
+/// contiguous buffer, framing each as `[u32 length][crc32 of payload][payload]` so a
+/// single block can be located and integrity-checked without touching its
+/// neighbors. Returns the buffer alongside an index of each block's offset and
+/// frame length — persist the index once per checkpoint to support lazy/partial
+/// checkout of a `.blend` when only a few blocks changed.
+pub fn write_framed_blocks(blocks: &[(String, Vec<u8>)]) -> (Vec<u8>, Vec<BlockFrameIndexEntry>) {
+    let mut buffer = Vec::new();
+    let mut index = Vec::with_capacity(blocks.len());
+
+    for (hash, payload) in blocks {
+        let offset = buffer.len() as u64;
+
+        buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        buffer.extend_from_slice(payload);
+
+        let length = (buffer.len() as u64 - offset) as u32;
+        index.push(BlockFrameIndexEntry {
+            hash: hash.clone(),
+            offset,
+            length,
+        });
+    }
+
+    (buffer, index)
+}
+
+/// Reads and CRC-checks a single block's frame out of a [`write_framed_blocks`]
+/// buffer, without decompressing or touching any other frame. The CRC is a cheap
+/// pre-check before the more expensive blake2b hash check — it catches bit-level
+/// corruption in the compressed bytes, but callers should still verify the
+/// decompressed content's hash to catch corruption the CRC can miss.
+pub fn read_framed_block(buffer: &[u8], entry: &BlockFrameIndexEntry) -> Result<Vec<u8>, Error> {
+    let frame = buffer
+        .get(entry.offset as usize..(entry.offset as usize + entry.length as usize))
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Frame index out of bounds"))?;
+
+    if frame.len() < 8 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Block frame too short"));
+    }
+
+    let length = u32::from_le_bytes(frame[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(frame[4..8].try_into().unwrap());
+    let payload = frame.get(8..8 + length).ok_or_else(|| {
+        Error::new(
+            ErrorKind::UnexpectedEof,
+            "Block frame length does not match its recorded payload size",
+        )
+    })?;
+
+    if crc32fast::hash(payload) != crc {
+        return Err(Error::new(ErrorKind::InvalidData, "Block frame CRC mismatch"));
+    }
+
+    Ok(payload.to_vec())
+}
+
+pub const ENCRYPTION_SALT_LEN: usize = 16;
+const ENCRYPTION_KEY_LEN: usize = 32;
+const ENCRYPTION_NONCE_LEN: usize = 12; // 96 bits, as recommended for both ciphers below
+
+/// AEAD cipher used to encrypt a stored block at rest. Persisted as a one-byte tag
+/// prefix (ahead of the nonce) so decryption doesn't need out-of-band config.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum EncryptionAlgo {
+    ChaCha20Poly1305 = 0,
+    Aes256Gcm = 1,
+}
+
+impl EncryptionAlgo {
+    fn from_tag(tag: u8) -> Result<Self, Error> {
+        match tag {
+            0 => Ok(EncryptionAlgo::ChaCha20Poly1305),
+            1 => Ok(EncryptionAlgo::Aes256Gcm),
+            other => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown encryption algo tag: {other}"),
+            )),
+        }
+    }
+}
+
+/// Hex-encodes a salt for storage in a text config column.
+pub fn salt_to_hex(salt: &[u8]) -> String {
+    salt.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reverses [`salt_to_hex`].
+pub fn salt_from_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    if hex.len() % 2 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "Odd-length salt hex"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Invalid salt hex: {e}")))
+        })
+        .collect()
+}
+
+/// Generates a fresh random per-store salt for [`derive_key_from_passphrase`]. Callers
+/// persist this once (e.g. alongside the store's other small config values) and reuse
+/// it for every block, so the same passphrase always derives the same key.
+pub fn generate_salt() -> [u8; ENCRYPTION_SALT_LEN] {
+    let mut salt = [0u8; ENCRYPTION_SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// Argon2 cost parameters used to derive a store's encryption key. Persisted
+/// alongside the salt (see [`kdf_params_to_string`]) rather than hardcoded, so a
+/// future change to the defaults doesn't change the key derived for stores that
+/// already picked a passphrase under the old ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+/// The cost parameters used for newly-encrypted stores.
+pub fn default_kdf_params() -> KdfParams {
+    let params = argon2::Params::default();
+    KdfParams {
+        m_cost: params.m_cost(),
+        t_cost: params.t_cost(),
+        p_cost: params.p_cost(),
+    }
+}
+
+/// Serializes [`KdfParams`] into the small text value the `config` table stores.
+pub fn kdf_params_to_string(params: &KdfParams) -> String {
+    format!("{}:{}:{}", params.m_cost, params.t_cost, params.p_cost)
+}
+
+/// Reverses [`kdf_params_to_string`].
+pub fn kdf_params_from_string(value: &str) -> Result<KdfParams, Error> {
+    let parse_error = || Error::new(ErrorKind::InvalidData, "Malformed KDF params");
+
+    let mut parts = value.split(':');
+    let m_cost: u32 = parts
+        .next()
+        .ok_or_else(parse_error)?
+        .parse()
+        .map_err(|_| parse_error())?;
+    let t_cost: u32 = parts
+        .next()
+        .ok_or_else(parse_error)?
+        .parse()
+        .map_err(|_| parse_error())?;
+    let p_cost: u32 = parts
+        .next()
+        .ok_or_else(parse_error)?
+        .parse()
+        .map_err(|_| parse_error())?;
+
+    Ok(KdfParams {
+        m_cost,
+        t_cost,
+        p_cost,
+    })
+}
+
+/// Derives a 256-bit key from a user passphrase via Argon2, using a per-store random
+/// salt (see [`generate_salt`]) and cost parameters (see [`default_kdf_params`]) so
+/// the same passphrase produces different keys across stores.
+pub fn derive_key_from_passphrase(
+    passphrase: &str,
+    salt: &[u8],
+    kdf_params: &KdfParams,
+) -> Result<[u8; ENCRYPTION_KEY_LEN], Error> {
+    let params = argon2::Params::new(
+        kdf_params.m_cost,
+        kdf_params.t_cost,
+        kdf_params.p_cost,
+        Some(ENCRYPTION_KEY_LEN),
+    )
+    .map_err(|e| Error::new(ErrorKind::Other, format!("Invalid Argon2 params: {e}")))?;
+
+    let mut key = [0u8; ENCRYPTION_KEY_LEN];
+    argon2::Argon2::new(argon2::Algorithm::default(), argon2::Version::default(), params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("Argon2 key derivation failed: {e}"),
+            )
+        })?;
+    Ok(key)
+}
+
+/// Encrypts already-compressed block bytes with an AEAD cipher. A fresh random nonce
+/// is generated per call and prepended to the ciphertext, along with a one-byte algo
+/// tag, so the result is self-describing: `[algo tag][nonce][ciphertext+auth tag]`.
+/// Must run after compression — encrypted bytes are indistinguishable from random and
+/// won't compress.
+pub fn encrypt_block(data: &[u8], key: &[u8; ENCRYPTION_KEY_LEN], algo: EncryptionAlgo) -> Vec<u8> {
+    let mut nonce_bytes = [0u8; ENCRYPTION_NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+
+    let ciphertext = match algo {
+        EncryptionAlgo::ChaCha20Poly1305 => {
+            let cipher = ChaCha20Poly1305::new(key.into());
+            cipher
+                .encrypt(&nonce_bytes.into(), data)
+                .expect("Encryption failure is not expected with a valid key/nonce")
+        }
+        EncryptionAlgo::Aes256Gcm => {
+            let cipher = Aes256Gcm::new(key.into());
+            cipher
+                .encrypt(&nonce_bytes.into(), data)
+                .expect("Encryption failure is not expected with a valid key/nonce")
+        }
+    };
+
+    let mut tagged = Vec::with_capacity(1 + ENCRYPTION_NONCE_LEN + ciphertext.len());
+    tagged.push(algo as u8);
+    tagged.extend_from_slice(&nonce_bytes);
+    tagged.extend(ciphertext);
+    tagged
+}
+
+/// Reverses [`encrypt_block`], returning the compressed bytes that were encrypted.
+/// The AEAD authentication tag doubles as tamper detection: a corrupted or wrong-key
+/// decrypt fails here instead of silently returning garbage.
+pub fn decrypt_block(tagged: &[u8], key: &[u8; ENCRYPTION_KEY_LEN]) -> Result<Vec<u8>, Error> {
+    if tagged.len() < 1 + ENCRYPTION_NONCE_LEN {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "Encrypted block payload too short",
+        ));
+    }
+
+    let algo = EncryptionAlgo::from_tag(tagged[0])?;
+    let nonce_bytes = &tagged[1..1 + ENCRYPTION_NONCE_LEN];
+    let ciphertext = &tagged[1 + ENCRYPTION_NONCE_LEN..];
+
+    let plaintext = match algo {
+        EncryptionAlgo::ChaCha20Poly1305 => {
+            let cipher = ChaCha20Poly1305::new(key.into());
+            cipher.decrypt(nonce_bytes.into(), ciphertext)
+        }
+        EncryptionAlgo::Aes256Gcm => {
+            let cipher = Aes256Gcm::new(key.into());
+            cipher.decrypt(nonce_bytes.into(), ciphertext)
+        }
+    }
+    .map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "Decryption failed: wrong key or corrupted/tampered block",
+        )
+    })?;
+
+    Ok(plaintext)
+}
+
 pub fn to_file_transactional(
     path: &str,
     blend_data: Vec<u8>,
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use once_cell::sync::Lazy;
+
+/// Default byte budget for the in-memory decompressed-block cache, overridable via
+/// `BLOCK_CACHE_BYTES`. Big enough to keep a handful of checkpoints' worth of hot
+/// blocks resident without growing unbounded on a long-lived process such as a sync
+/// server handling several clone requests back to back.
+const DEFAULT_CACHE_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+fn cache_budget_bytes() -> usize {
+    std::env::var("BLOCK_CACHE_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CACHE_BUDGET_BYTES)
+}
+
+struct Entry {
+    data: Vec<u8>,
+    // Bumped on every access; the entry with the smallest value is the one evicted,
+    // so this stands in for a proper LRU linked list without needing one.
+    last_used: u64,
+}
+
+/// Process-wide cache of already-decompressed block bytes, keyed by block hash.
+/// Blocks are immutable and content-addressed, so a cache hit is always correct and
+/// never needs invalidation. Bounded by total decompressed byte size rather than
+/// entry count, since block sizes vary wildly.
+pub struct BlockCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    budget_bytes: usize,
+    clock: Mutex<u64>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockCache {
+    fn new(budget_bytes: usize) -> BlockCache {
+        BlockCache {
+            entries: Mutex::new(HashMap::new()),
+            budget_bytes,
+            clock: Mutex::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let mut clock = self.clock.lock().unwrap();
+        *clock += 1;
+        *clock
+    }
+
+    /// Looks up already-decompressed bytes for `hash`, counting the lookup towards
+    /// the hit-rate reported by [`BlockCache::hit_rate`].
+    pub fn get(&self, hash: &str) -> Option<Vec<u8>> {
+        let last_used = self.tick();
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(hash) {
+            Some(entry) => {
+                entry.last_used = last_used;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.data.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Inserts freshly-decompressed bytes for `hash`, evicting the least-recently-used
+    /// entries until the cache is back under budget. A block bigger than the whole
+    /// budget can never fit, so it's left uncached rather than evicting everything
+    /// else to make room for it.
+    pub fn insert(&self, hash: String, data: Vec<u8>) {
+        if data.len() > self.budget_bytes {
+            return;
+        }
+
+        let last_used = self.tick();
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(hash, Entry { data, last_used });
+
+        let mut total: usize = entries.values().map(|e| e.data.len()).sum();
+        while total > self.budget_bytes {
+            let lru_hash = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(hash, _)| hash.clone());
+
+            match lru_hash {
+                Some(hash) => {
+                    if let Some(evicted) = entries.remove(&hash) {
+                        total -= evicted.data.len();
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Hit rate (0.0-1.0) across every `get` call since process start, or `None` if
+    /// `get` has never been called.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let total = hits + misses;
+        if total == 0 {
+            None
+        } else {
+            Some(hits as f64 / total as f64)
+        }
+    }
+}
+
+/// The process-wide cache instance. Shared across every `restore_checkpoint` call in
+/// the process, so switching back and forth between two branches whose tips share
+/// most blocks only pays the decompression cost once per hash.
+pub static BLOCK_CACHE: Lazy<BlockCache> = Lazy::new(|| BlockCache::new(cache_budget_bytes()));
@@ -0,0 +1,227 @@
+use rusqlite::OptionalExtension;
+
+use super::{
+    db_ops::{DBError, Persistence, DB},
+    structs::BlockRecord,
+};
+
+/// Content-addressed block storage, split out from [`DB`] so the large compressed
+/// block blobs `restore_checkpoint`'s `par_iter` reads don't have to live in the same
+/// engine as the commit graph and branch pointers. A project big enough to feel
+/// `read_blocks` as the bottleneck can swap in a store built for large-value random
+/// reads without touching `Persistence`'s relational schema at all.
+pub trait BlockStore: Send + Sync {
+    fn write_blocks(&self, blocks: &[BlockRecord]) -> anyhow::Result<()>;
+    fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>>;
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool>;
+
+    /// Increments the stored refcount for each hash, creating it at `1` if this is
+    /// the first commit to reference it. Since blocks are deduplicated across
+    /// commits, this -- not mere presence in the store -- is what pruning a commit
+    /// or branch consults to decide whether a block is still needed by anything else.
+    fn increment_refcounts(&self, hashes: &[String]) -> anyhow::Result<()>;
+
+    /// Decrements the stored refcount for each hash, physically deleting any block
+    /// whose count reaches zero. A hash with no refcount row yet (written before
+    /// this scheme existed) is treated as already at zero and deleted outright.
+    fn decrement_refcounts(&self, hashes: &[String]) -> anyhow::Result<()>;
+}
+
+/// Namespace prefix for refcount keys in the `blocks` table -- shared with
+/// [`is_block_refcount_key`] so a scan over that table (e.g. garbage collection's
+/// "every stored hash") can tell a bookkeeping row from actual block content.
+const BLOCK_REFCOUNT_KEY_PREFIX: &str = "block-rc-";
+
+/// Key a block's refcount is stored under, namespaced so it can't collide with the
+/// hash-keyed block content it counts references to.
+fn block_refcount_key(hash: &str) -> String {
+    format!("{}{}", BLOCK_REFCOUNT_KEY_PREFIX, hash)
+}
+
+/// True for a `blocks` table key that holds a refcount rather than block content --
+/// `SqliteBlockStore` is the only driver that reuses that table for both, so a caller
+/// scanning every key in it (e.g. [`super::super::api::gc_command::gc`]) needs this to
+/// avoid treating a refcount row as an orphaned block.
+pub(crate) fn is_block_refcount_key(key: &str) -> bool {
+    key.starts_with(BLOCK_REFCOUNT_KEY_PREFIX)
+}
+
+/// Default driver: blocks live in the `blocks` table of the same SQLite file as
+/// commits and branches, exactly as `Persistence` has always stored them. Opens its
+/// own connection per call rather than borrowing one, so it can sit behind a
+/// `Box<dyn BlockStore>` without tying its lifetime to a particular `Persistence`.
+pub struct SqliteBlockStore {
+    db_path: String,
+}
+
+impl SqliteBlockStore {
+    pub fn new(db_path: &str) -> SqliteBlockStore {
+        SqliteBlockStore {
+            db_path: db_path.to_owned(),
+        }
+    }
+}
+
+impl BlockStore for SqliteBlockStore {
+    fn write_blocks(&self, blocks: &[BlockRecord]) -> anyhow::Result<()> {
+        let mut conn = Persistence::open(&self.db_path)?;
+        conn.execute_in_transaction(|tx| Persistence::write_blocks(tx, blocks))
+    }
+
+    fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>> {
+        let conn = Persistence::open(&self.db_path)?;
+        conn.read_blocks(hashes)
+    }
+
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        let conn = Persistence::open(&self.db_path)?;
+        conn.check_block_exists(hash)
+    }
+
+    fn increment_refcounts(&self, hashes: &[String]) -> anyhow::Result<()> {
+        let mut conn = Persistence::open(&self.db_path)?;
+        conn.execute_in_transaction(|tx| {
+            for hash in hashes {
+                let count = read_refcount(tx, hash)?;
+                write_refcount(tx, hash, count + 1)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn decrement_refcounts(&self, hashes: &[String]) -> anyhow::Result<()> {
+        let mut conn = Persistence::open(&self.db_path)?;
+        conn.execute_in_transaction(|tx| {
+            for hash in hashes {
+                let count = read_refcount(tx, hash)?;
+                if count <= 1 {
+                    tx.execute(
+                        "DELETE FROM blocks WHERE key IN (?1, ?2)",
+                        rusqlite::params![block_refcount_key(hash), hash],
+                    )?;
+                } else {
+                    write_refcount(tx, hash, count - 1)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+fn read_refcount(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<i64> {
+    let stored: Option<Vec<u8>> = tx
+        .query_row(
+            "SELECT value FROM blocks WHERE key = ?1",
+            [block_refcount_key(hash)],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(stored
+        .map(|bytes| String::from_utf8_lossy(&bytes).parse::<i64>().unwrap_or(0))
+        .unwrap_or(0))
+}
+
+fn write_refcount(tx: &rusqlite::Transaction, hash: &str, count: i64) -> anyhow::Result<()> {
+    tx.execute(
+        "INSERT INTO blocks (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        rusqlite::params![block_refcount_key(hash), count.to_string().into_bytes()],
+    )?;
+    Ok(())
+}
+
+/// Embedded key-value driver: blocks live in a RocksDB instance keyed by hash, next to
+/// (not inside) the relational store. A block read no longer contends with SQLite's
+/// single-writer lock, and an LSM tree's large-value random reads don't have to share
+/// a B-tree page cache with commit and branch lookups.
+pub struct RocksBlockStore {
+    db: rocksdb::DB,
+}
+
+impl RocksBlockStore {
+    pub fn open(dir: &str) -> anyhow::Result<RocksBlockStore> {
+        let db = rocksdb::DB::open_default(dir)?;
+        Ok(RocksBlockStore { db })
+    }
+
+    fn rocks_refcount(&self, hash: &str) -> anyhow::Result<i64> {
+        Ok(self
+            .db
+            .get(block_refcount_key(hash))?
+            .map(|bytes| String::from_utf8_lossy(&bytes).parse::<i64>().unwrap_or(0))
+            .unwrap_or(0))
+    }
+}
+
+impl BlockStore for RocksBlockStore {
+    fn write_blocks(&self, blocks: &[BlockRecord]) -> anyhow::Result<()> {
+        for block in blocks {
+            // Blocks are content-addressed and immutable, so a key that's already
+            // present already holds the right bytes -- skip the write instead of
+            // re-writing an identical value.
+            if self.db.get(&block.hash)?.is_none() {
+                self.db.put(&block.hash, &block.data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>> {
+        hashes
+            .into_iter()
+            .map(|hash| {
+                let data = self.db.get(&hash)?.ok_or_else(|| {
+                    anyhow::anyhow!(DBError::Consistency(format!(
+                        "No block with hash {:?} found in block store",
+                        hash
+                    )))
+                })?;
+                Ok(BlockRecord { hash, data })
+            })
+            .collect()
+    }
+
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        Ok(self.db.get(hash)?.is_some())
+    }
+
+    fn increment_refcounts(&self, hashes: &[String]) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for hash in hashes {
+            let count = self.rocks_refcount(hash)?;
+            batch.put(block_refcount_key(hash), (count + 1).to_string());
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    fn decrement_refcounts(&self, hashes: &[String]) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for hash in hashes {
+            let count = self.rocks_refcount(hash)?;
+            if count <= 1 {
+                batch.delete(block_refcount_key(hash));
+                batch.delete(hash);
+            } else {
+                batch.put(block_refcount_key(hash), (count - 1).to_string());
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+}
+
+/// Picks a block-storage driver from environment config: `BLOCK_STORE_BACKEND=rocksdb`
+/// opts into the embedded key-value driver (`BLOCK_STORE_ROCKSDB_PATH` for its
+/// directory, defaulting to `<db_path>.blocks.rocksdb`), anything else -- including
+/// unset -- keeps blocks in the same SQLite file `db_path` points at.
+pub fn open_block_store(db_path: &str) -> anyhow::Result<Box<dyn BlockStore>> {
+    match std::env::var("BLOCK_STORE_BACKEND").as_deref() {
+        Ok("rocksdb") => {
+            let rocks_path = std::env::var("BLOCK_STORE_ROCKSDB_PATH")
+                .unwrap_or_else(|_| format!("{}.blocks.rocksdb", db_path));
+            Ok(Box::new(RocksBlockStore::open(&rocks_path)?))
+        }
+        _ => Ok(Box::new(SqliteBlockStore::new(db_path))),
+    }
+}
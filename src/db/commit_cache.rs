@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::structs::Commit;
+
+/// Default number of entries kept per map, overridable via `COMMIT_CACHE_CAPACITY`.
+/// Branch tips and commit rows are small and roughly uniform in size, so (unlike
+/// [`super::block_cache::BlockCache`]'s byte budget) an entry count is bound enough.
+const DEFAULT_CACHE_CAPACITY: usize = 512;
+
+fn cache_capacity() -> usize {
+    std::env::var("COMMIT_CACHE_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CACHE_CAPACITY)
+}
+
+struct Entry<T> {
+    value: T,
+    last_used: u64,
+}
+
+struct BoundedMap<T> {
+    entries: HashMap<String, Entry<T>>,
+    capacity: usize,
+}
+
+impl<T> BoundedMap<T> {
+    fn new(capacity: usize) -> BoundedMap<T> {
+        BoundedMap {
+            entries: HashMap::new(),
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str, clock: u64) -> Option<&T>
+    where
+        T: Clone,
+    {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = clock;
+                Some(&entry.value)
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: String, value: T, clock: u64) {
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                last_used: clock,
+            },
+        );
+
+        while self.entries.len() > self.capacity {
+            let lru_key = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+
+            match lru_key {
+                Some(key) => {
+                    self.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Read-through cache for the lookups `create_new_checkpoint` and checkpoint-log
+/// traversal repeat most as history grows: a branch's tip hash, the current-commit
+/// pointer, and already-read commit rows. There's no scheme for telling, from inside
+/// `execute_in_transaction`'s generic closure, exactly which of these a given write
+/// touched, so [`CommitCache::invalidate_all`] is called instead of tracking
+/// per-write invalidation -- correctness over a marginal hit-rate gain, since a write
+/// transaction is already far rarer than the reads it would otherwise make stale.
+pub struct CommitCache {
+    branch_tips: Mutex<BoundedMap<Option<String>>>,
+    commits: Mutex<BoundedMap<Option<Commit>>>,
+    current_commit_pointer: Mutex<Option<String>>,
+    clock: Mutex<u64>,
+}
+
+impl CommitCache {
+    pub fn new() -> CommitCache {
+        let capacity = cache_capacity();
+        CommitCache {
+            branch_tips: Mutex::new(BoundedMap::new(capacity)),
+            commits: Mutex::new(BoundedMap::new(capacity)),
+            current_commit_pointer: Mutex::new(None),
+            clock: Mutex::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let mut clock = self.clock.lock().unwrap();
+        *clock += 1;
+        *clock
+    }
+
+    pub fn get_branch_tip(&self, branch_name: &str) -> Option<Option<String>> {
+        let clock = self.tick();
+        self.branch_tips
+            .lock()
+            .unwrap()
+            .get(branch_name, clock)
+            .cloned()
+    }
+
+    pub fn put_branch_tip(&self, branch_name: &str, tip: Option<String>) {
+        let clock = self.tick();
+        self.branch_tips
+            .lock()
+            .unwrap()
+            .insert(branch_name.to_string(), tip, clock);
+    }
+
+    pub fn get_commit(&self, hash: &str) -> Option<Option<Commit>> {
+        let clock = self.tick();
+        self.commits.lock().unwrap().get(hash, clock).cloned()
+    }
+
+    pub fn put_commit(&self, hash: &str, commit: Option<Commit>) {
+        let clock = self.tick();
+        self.commits
+            .lock()
+            .unwrap()
+            .insert(hash.to_string(), commit, clock);
+    }
+
+    pub fn get_current_commit_pointer(&self) -> Option<String> {
+        self.current_commit_pointer.lock().unwrap().clone()
+    }
+
+    pub fn put_current_commit_pointer(&self, hash: String) {
+        *self.current_commit_pointer.lock().unwrap() = Some(hash);
+    }
+
+    /// Drops every cached branch tip, commit row, and the current-commit pointer.
+    /// Called once per committed write transaction -- see the struct doc comment for
+    /// why that's coarser than per-key invalidation.
+    pub fn invalidate_all(&self) {
+        self.branch_tips.lock().unwrap().clear();
+        self.commits.lock().unwrap().clear();
+        self.current_commit_pointer.lock().unwrap().take();
+    }
+}
+
+impl Default for CommitCache {
+    fn default() -> Self {
+        CommitCache::new()
+    }
+}
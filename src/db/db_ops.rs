@@ -1,14 +1,68 @@
 use anyhow::{bail, Context};
-use std::{error::Error, fmt::Display};
+use rusqlite::OptionalExtension;
+use std::{collections::HashMap, error::Error, fmt::Display};
 
+use super::commit_cache::CommitCache;
+use super::migrations::MIGRATIONS;
 use super::structs::{BlockRecord, Commit};
 
+/// SQLite's hard limit on bound parameters per statement (`SQLITE_LIMIT_VARIABLE_NUMBER`'s
+/// conservative default). `read_blocks` chunks its `IN (...)` queries to this size so a
+/// single checkpoint's worth of block hashes never blows past it.
+pub(crate) const SQLITE_MAX_VARIABLE_NUMBER: usize = 999;
+
+#[derive(Debug, Clone)]
 pub struct ShortCommitRecord {
     pub hash: String,
     pub branch: String,
     pub message: String,
 }
 
+/// Maps a `rusqlite::Row` onto `Self` by column index, so a `SELECT` that drifts out
+/// of sync with a type -- a dropped column, a NULL where none was expected -- comes
+/// back as a `rusqlite::Error` propagated with `?` instead of panicking through
+/// `.expect()`. Each query's `SELECT` list has to match the column order an impl
+/// reads in, but that ordering now lives in exactly one place per type.
+pub(crate) trait FromRow: Sized {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self>;
+}
+
+impl FromRow for Commit {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Commit {
+            hash: row.get(0)?,
+            prev_commit_hash: row.get(1)?,
+            project_id: row.get(2)?,
+            branch: row.get(3)?,
+            message: row.get(4)?,
+            author: row.get(5)?,
+            date: row.get(6)?,
+            header: row.get(7)?,
+            blocks_and_pointers: row.get(8)?,
+        })
+    }
+}
+
+impl FromRow for ShortCommitRecord {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(ShortCommitRecord {
+            hash: row.get(0)?,
+            branch: row.get(1)?,
+            message: row.get(2)?,
+        })
+    }
+}
+
+/// Persisted state for an in-progress bisect session: the ordered candidate hashes
+/// from known-good to known-bad, and the current `[low, high]` index bounds into
+/// that list. Surviving in the DB means the session carries across separate FFI
+/// calls instead of needing to stay alive in process memory between steps.
+pub struct BisectState {
+    pub candidates: Vec<String>,
+    pub low: usize,
+    pub high: usize,
+}
+
 #[derive(Debug)]
 pub enum DBError {
     Fundamental(String), // means that stuff is very wrong
@@ -31,20 +85,87 @@ impl Display for DBError {
 pub trait DB: Sized {
     fn open(path: &str) -> anyhow::Result<Self>;
 
+    /// Opens the store exactly like `open`, except the SQLite file itself -- not
+    /// just block contents -- is encrypted at rest: `PRAGMA key` is issued against
+    /// the raw connection before any table creation or migration runs, backed by
+    /// `rusqlite`'s `sqlcipher` feature. `open` stays the unencrypted default for
+    /// compatibility; only stores created via this path (or `rekey`'d into one) can
+    /// be reopened with it.
+    fn open_encrypted(path: &str, passphrase: &str) -> anyhow::Result<Self>;
+
+    /// Changes the passphrase of an already-open encrypted store via `PRAGMA rekey`.
+    /// Calling this on a store that wasn't opened with `open_encrypted` corrupts it.
+    fn rekey(&self, new_passphrase: &str) -> anyhow::Result<()>;
+
+    /// Whether this timeline's SQLite file is SQLCipher-encrypted, so tooling can
+    /// prompt for a passphrase before attempting to open it rather than failing with
+    /// an opaque "file is not a database" error.
+    fn read_is_db_encrypted(&self) -> anyhow::Result<bool>;
+    fn write_is_db_encrypted(tx: &rusqlite::Transaction) -> anyhow::Result<()>;
+
     fn write_blocks(tx: &rusqlite::Transaction, blocks: &[BlockRecord]) -> anyhow::Result<()>;
     fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>>;
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool>;
+    fn read_all_block_hashes(&self) -> anyhow::Result<Vec<String>>;
+    /// Unlike [`DB::write_blocks`], overwrites an existing block rather than leaving
+    /// it untouched -- used to repair a block whose content no longer matches its hash.
+    fn overwrite_block(tx: &rusqlite::Transaction, block: &BlockRecord) -> anyhow::Result<()>;
+    /// Unconditionally removes blocks by hash -- used by garbage collection once a
+    /// hash is confirmed unreachable from any live commit.
+    fn delete_blocks(tx: &rusqlite::Transaction, hashes: &[String]) -> anyhow::Result<()>;
 
     fn write_commit(tx: &rusqlite::Transaction, commit: Commit) -> anyhow::Result<()>;
     fn read_commit(&self, hash: &str) -> anyhow::Result<Option<Commit>>;
     fn check_commit_exists(&self, hash: &str) -> anyhow::Result<bool>;
 
+    /// Records an ed25519 `(author_pubkey, signature)` pair over a commit's content
+    /// hash. Kept in its own table rather than as columns on `commits`, since signing
+    /// is opt-in and most commits will never have a row here.
+    fn write_commit_signature(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        author_pubkey: &[u8],
+        signature: &[u8],
+    ) -> anyhow::Result<()>;
+    /// `(author_pubkey, signature)` for `commit_hash`, or `None` if it was never
+    /// signed.
+    fn read_commit_signature(&self, commit_hash: &str)
+        -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>>;
+
     fn read_ancestors_of_commit(
         &self,
         starting_from_hash: &str,
     ) -> anyhow::Result<Vec<ShortCommitRecord>>;
 
+    /// Bounded version of [`DB::read_ancestors_of_commit`] for rendering a scrollable
+    /// log: the walk stops `max_depth - 1` hops from `starting_from_hash` (at most
+    /// `max_depth` levels, counting the start commit) rather than reaching the root, and `before_date` (when given) lets a caller page further
+    /// back by resuming just past the oldest commit returned by the previous page.
+    fn read_ancestors_page(
+        &self,
+        starting_from_hash: &str,
+        max_depth: i64,
+        before_date: Option<i64>,
+    ) -> anyhow::Result<Vec<ShortCommitRecord>>;
+
     fn read_descendants_of_commit(&self, hash: &str) -> anyhow::Result<Vec<Commit>>;
 
+    /// Every commit created at or after `since_date` (a Unix timestamp), regardless
+    /// of reachability from a live branch tip -- used by garbage collection to spare
+    /// very recent commits that aren't pointed at by a branch yet.
+    fn read_commits_since(&self, since_date: i64) -> anyhow::Result<Vec<Commit>>;
+
+    /// Every commit stored with `branch_name` in its `branch` column -- the same set
+    /// [`DB::delete_branch_with_commits`] removes -- so a caller can gather the blocks
+    /// those commits reference before pruning the branch.
+    fn read_commits_for_branch(&self, branch_name: &str) -> anyhow::Result<Vec<Commit>>;
+
+    /// Removes a single commit row by hash. Unlike [`DB::delete_branch_with_commits`],
+    /// leaves the branch and every other commit on it untouched -- for pruning one
+    /// stray checkpoint (e.g. one superseded by a refcount-based GC) rather than a
+    /// whole branch.
+    fn delete_commit(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<()>;
+
     fn read_current_branch_name(&self) -> anyhow::Result<String>;
     fn write_current_branch_name(
         tx: &rusqlite::Transaction,
@@ -75,11 +196,61 @@ pub trait DB: Sized {
     fn read_name(&self) -> anyhow::Result<Option<String>>;
     fn write_name(tx: &rusqlite::Transaction, name: &str) -> anyhow::Result<()>;
 
+    /// Per-store salt for deriving an at-rest encryption key from a user passphrase.
+    /// `None` until the first time encryption is enabled for this store.
+    fn read_encryption_salt(&self) -> anyhow::Result<Option<String>>;
+    fn write_encryption_salt(tx: &rusqlite::Transaction, salt_hex: &str) -> anyhow::Result<()>;
+
+    /// Argon2 cost parameters the store's encryption key was derived with. Persisted
+    /// alongside the salt so changing the library's defaults later doesn't silently
+    /// change the key derived for a store that already chose a passphrase.
+    fn read_encryption_kdf_params(&self) -> anyhow::Result<Option<String>>;
+    fn write_encryption_kdf_params(
+        tx: &rusqlite::Transaction,
+        kdf_params: &str,
+    ) -> anyhow::Result<()>;
+
+    /// `None` when no bisect session is in progress for this store.
+    fn read_bisect_state(&self) -> anyhow::Result<Option<BisectState>>;
+    fn write_bisect_state(tx: &rusqlite::Transaction, state: &BisectState) -> anyhow::Result<()>;
+    fn clear_bisect_state(tx: &rusqlite::Transaction) -> anyhow::Result<()>;
+
+    /// Second parent of a merge commit. Ordinary commits have none; kept in a side
+    /// table rather than on `commits` itself so non-merge commits (the overwhelming
+    /// majority) pay nothing for a column they never use.
+    fn read_merge_parent(&self, commit_hash: &str) -> anyhow::Result<Option<String>>;
+    fn write_merge_parent(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        second_parent_hash: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Every parent of `commit_hash`, in recorded order: just `[prev_commit_hash]`
+    /// for an ordinary commit, `[ours_tip, theirs_tip]` for one written by
+    /// `create_merge_checkpoint`. `write_commit` keeps this in sync with
+    /// `prev_commit_hash` for every commit it writes, so callers that only ever
+    /// create ordinary commits never have to call `write_commit_parents` directly --
+    /// it exists so a merge commit can record its second (and further) parent
+    /// without `commits` itself growing a variable number of columns. Backs the
+    /// ancestor/descendant traversals below instead of the `prev_commit_hash`
+    /// column, so a merge commit's full ancestry is reachable through either parent.
+    fn read_parents_of_commit(&self, commit_hash: &str) -> anyhow::Result<Vec<String>>;
+    fn write_commit_parents(
+        tx: &rusqlite::Transaction,
+        child_hash: &str,
+        parent_hashes: &[String],
+    ) -> anyhow::Result<()>;
+
     fn delete_branch_with_commits(
         tx: &rusqlite::Transaction,
         branch_name: &str,
     ) -> anyhow::Result<()>;
 
+    /// `0` for a store that predates the migrations subsystem entirely (no
+    /// `schema_version` row has ever been written).
+    fn read_schema_version(&self) -> anyhow::Result<i64>;
+    fn write_schema_version(tx: &rusqlite::Transaction, version: i64) -> anyhow::Result<()>;
+
     fn execute_in_transaction<F>(&mut self, f: F) -> anyhow::Result<()>
     where
         F: FnOnce(&rusqlite::Transaction) -> anyhow::Result<()>;
@@ -87,6 +258,7 @@ pub trait DB: Sized {
 
 pub struct Persistence {
     sqlite_db: rusqlite::Connection,
+    cache: CommitCache,
 }
 
 #[inline]
@@ -114,6 +286,41 @@ fn user_name_key() -> String {
     "USER_NAME".to_string()
 }
 
+#[inline]
+fn encryption_salt_key() -> String {
+    "ENCRYPTION_SALT".to_string()
+}
+
+#[inline]
+fn encryption_kdf_params_key() -> String {
+    "ENCRYPTION_KDF_PARAMS".to_string()
+}
+
+#[inline]
+fn db_encrypted_key() -> String {
+    "DB_ENCRYPTED".to_string()
+}
+
+#[inline]
+fn bisect_candidates_key() -> String {
+    "BISECT_CANDIDATES".to_string()
+}
+
+#[inline]
+fn bisect_low_key() -> String {
+    "BISECT_LOW".to_string()
+}
+
+#[inline]
+fn bisect_high_key() -> String {
+    "BISECT_HIGH".to_string()
+}
+
+#[inline]
+fn schema_version_key() -> String {
+    "SCHEMA_VERSION".to_string()
+}
+
 fn write_config_inner(tx: &rusqlite::Transaction, key: &str, value: &str) -> anyhow::Result<()> {
     tx.execute(
         "INSERT OR REPLACE INTO config (key, value) VALUES (?1, ?2)",
@@ -138,54 +345,113 @@ fn read_config_inner(conn: &rusqlite::Connection, key: &str) -> anyhow::Result<O
     }
 }
 
+/// Shared body of `open`/`open_encrypted`: opens the connection, `PRAGMA key`s it
+/// first when `passphrase` is given (SQLCipher only decrypts pages once the
+/// connection has been keyed, so this has to happen before any table creation or
+/// migration runs), creates tables, and runs pending migrations.
+fn open_inner(sqlite_path: &str, passphrase: Option<&str>) -> anyhow::Result<Persistence> {
+    let sqlite_db = rusqlite::Connection::open(sqlite_path)?;
+
+    if let Some(passphrase) = passphrase {
+        sqlite_db.pragma_update(None, "key", passphrase)?;
+    }
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS commits (
+                hash TEXT PRIMARY KEY,
+                prev_commit_hash TEXT,
+                project_id TEXT,
+                branch TEXT,
+                message TEXT,
+                author TEXT,
+                date INTEGER,
+                header BLOB,
+                blocks_and_pointers BLOB
+            )",
+        [],
+    )?;
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS branches (
+                name TEXT PRIMARY KEY,
+                tip TEXT
+            )",
+        [],
+    )?;
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS blocks (
+                key TEXT PRIMARY KEY,
+                value BLOB
+            )",
+        [],
+    )?;
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS config (
+                key TEXT PRIMARY KEY,
+                value TEXT
+            )",
+        [],
+    )?;
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS merge_parents (
+                commit_hash TEXT PRIMARY KEY,
+                second_parent_hash TEXT
+            )",
+        [],
+    )?;
+
+    sqlite_db.execute(
+        "CREATE TABLE IF NOT EXISTS commit_parents (
+                child_hash TEXT,
+                parent_hash TEXT,
+                ordinal INTEGER,
+                PRIMARY KEY (child_hash, ordinal)
+            )",
+        [],
+    )?;
+
+    let mut db = Persistence {
+        sqlite_db,
+        cache: CommitCache::new(),
+    };
+    db.run_pending_migrations()
+        .context("Cannot run pending schema migrations")?;
+
+    if passphrase.is_some() {
+        db.execute_in_transaction(Persistence::write_is_db_encrypted)?;
+    }
+
+    Ok(db)
+}
+
 impl DB for Persistence {
     fn open(sqlite_path: &str) -> anyhow::Result<Self> {
-        let sqlite_db = rusqlite::Connection::open(sqlite_path)?;
-
-        sqlite_db.execute(
-            "CREATE TABLE IF NOT EXISTS commits (
-                    hash TEXT PRIMARY KEY,
-                    prev_commit_hash TEXT,
-                    project_id TEXT,
-                    branch TEXT,
-                    message TEXT,
-                    author TEXT,
-                    date INTEGER,
-                    header BLOB,
-                    blocks_and_pointers BLOB
-                )",
-            [],
-        )?;
+        open_inner(sqlite_path, None)
+    }
 
-        sqlite_db.execute(
-            "CREATE TABLE IF NOT EXISTS branches (
-                    name TEXT PRIMARY KEY,
-                    tip TEXT
-                )",
-            [],
-        )?;
+    fn open_encrypted(sqlite_path: &str, passphrase: &str) -> anyhow::Result<Self> {
+        open_inner(sqlite_path, Some(passphrase))
+    }
 
-        sqlite_db.execute(
-            "CREATE TABLE IF NOT EXISTS blocks (
-                    key TEXT PRIMARY KEY,
-                    value BLOB
-                )",
-            [],
-        )?;
+    fn rekey(&self, new_passphrase: &str) -> anyhow::Result<()> {
+        self.sqlite_db
+            .pragma_update(None, "rekey", new_passphrase)
+            .context("Cannot rekey database")
+    }
 
-        sqlite_db.execute(
-            "CREATE TABLE IF NOT EXISTS config (
-                    key TEXT PRIMARY KEY,
-                    value TEXT
-                )",
-            [],
-        )?;
+    fn read_is_db_encrypted(&self) -> anyhow::Result<bool> {
+        Ok(read_config_inner(&self.sqlite_db, &db_encrypted_key())?.is_some())
+    }
 
-        Ok(Self { sqlite_db })
+    fn write_is_db_encrypted(tx: &rusqlite::Transaction) -> anyhow::Result<()> {
+        write_config_inner(tx, &db_encrypted_key(), "1")
     }
 
     fn write_blocks(tx: &rusqlite::Transaction, blocks: &[BlockRecord]) -> anyhow::Result<()> {
-        let mut stmt = tx.prepare(
+        let mut stmt = tx.prepare_cached(
             "INSERT INTO blocks (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO NOTHING",
         )?;
 
@@ -197,27 +463,99 @@ impl DB for Persistence {
     }
 
     fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>> {
-        let mut result: Vec<BlockRecord> = Vec::new();
-        for hash in hashes {
-            let block_data = self.sqlite_db.query_row(
-                "SELECT value FROM blocks WHERE key = ?1",
-                [&hash],
-                |row| Ok(Some(row.get(0).expect("No value in row"))),
-            )?;
-            if block_data.is_none() {
-                bail!(DBError::Error("No block with hash found".to_owned()))
-            } else {
-                result.push(BlockRecord {
-                    hash,
-                    data: block_data.unwrap(),
-                })
+        // SQLite caps the number of bound parameters per statement at ~999, so a
+        // single `IN (...)` can't hold an arbitrarily large hash list -- chunk it,
+        // trading a handful of round trips for avoiding the old one-`query_row`-per-hash
+        // loop, which was the actual bottleneck when a commit references thousands of
+        // blocks.
+        let mut by_hash: HashMap<String, Vec<u8>> = HashMap::with_capacity(hashes.len());
+
+        for chunk in hashes.chunks(SQLITE_MAX_VARIABLE_NUMBER) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let query = format!("SELECT key, value FROM blocks WHERE key IN ({placeholders})");
+
+            let mut stmt = self.sqlite_db.prepare_cached(&query)?;
+            let params = rusqlite::params_from_iter(chunk.iter());
+            let mut rows = stmt.query(params)?;
+
+            while let Some(row) = rows.next()? {
+                by_hash.insert(row.get(0)?, row.get(1)?);
             }
         }
 
-        Ok(result)
+        let missing: Vec<&String> = hashes.iter().filter(|h| !by_hash.contains_key(*h)).collect();
+        if !missing.is_empty() {
+            bail!(DBError::Consistency(format!(
+                "No block found for hashes: {:?}",
+                missing
+            )));
+        }
+
+        // Preserve the order blocks were requested in (and tolerate the same hash
+        // being requested more than once, which callers that chunk-dedup blocks
+        // across several commits can legitimately do), rather than whatever order
+        // SQLite happened to return rows.
+        Ok(hashes
+            .into_iter()
+            .map(|hash| {
+                let data = by_hash.get(&hash).expect("checked missing above").clone();
+                BlockRecord { hash, data }
+            })
+            .collect())
+    }
+
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare("SELECT key FROM blocks WHERE key = ?1")
+            .context("Cannot create statement")?;
+
+        let mut rows = stmt.query([hash]).context("Cannot query blocks")?;
+
+        Ok(rows.next()?.is_some())
+    }
+
+    fn read_all_block_hashes(&self) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare("SELECT key FROM blocks")
+            .context("Cannot create statement")?;
+
+        let hashes = stmt
+            .query_map([], |row| row.get(0))
+            .context("Cannot query blocks")?
+            .collect::<Result<Vec<String>, _>>()
+            .context("Cannot read block hash")?;
+
+        Ok(hashes)
+    }
+
+    fn overwrite_block(tx: &rusqlite::Transaction, block: &BlockRecord) -> anyhow::Result<()> {
+        tx.execute(
+            "INSERT INTO blocks (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            (&block.hash, &block.data),
+        )
+        .context("Cannot overwrite block")?;
+
+        Ok(())
+    }
+
+    fn delete_blocks(tx: &rusqlite::Transaction, hashes: &[String]) -> anyhow::Result<()> {
+        let mut stmt = tx
+            .prepare("DELETE FROM blocks WHERE key = ?1")
+            .context("Cannot prepare delete statement")?;
+
+        for hash in hashes {
+            stmt.execute([hash]).context("Cannot delete block")?;
+        }
+
+        Ok(())
     }
 
     fn write_commit(tx: &rusqlite::Transaction, commit: Commit) -> anyhow::Result<()> {
+        let hash = commit.hash.clone();
+        let prev_commit_hash = commit.prev_commit_hash.clone();
+
         tx.execute(
             "INSERT INTO commits (hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
             (
@@ -233,27 +571,31 @@ impl DB for Persistence {
             ),
         ).context("Cannot insert commit object")?;
 
+        // Every commit has at least its first parent; a merge commit's further
+        // parents are added with a follow-up `write_commit_parents` call that
+        // overwrites this same `ordinal = 0` entry with the identical value.
+        Persistence::write_commit_parents(tx, &hash, &[prev_commit_hash])?;
+
         Ok(())
     }
 
     fn read_commit(&self, hash: &str) -> anyhow::Result<Option<Commit>> {
-        self.sqlite_db.query_row("SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE hash = ?1", [hash], |row| Ok(Some(Commit {
-            hash: row.get(0).expect("No hash found in row"),
-            prev_commit_hash: row.get(1).expect("No prev_commit_hash found in row"),
-            project_id: row.get(2).expect("No project_id found in row"),
-            branch: row.get(3).expect("No branch found in row"),
-            message: row.get(4).expect("No message found in row"),
-            author: row.get(5).expect("No author found in row"),
-            date: row.get(6).expect("No date found in row"),
-            header: row.get(7).expect("No header found in row"),
-            blocks_and_pointers: row.get(8).expect("No blocks found in row")
-        }))).context("Cannot read commit")
+        if let Some(cached) = self.cache.get_commit(hash) {
+            return Ok(cached);
+        }
+
+        let commit = self.sqlite_db.query_row("SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE hash = ?1", [hash], |row| {
+            Commit::from_row(row)
+        }).optional().context("Cannot read commit")?;
+
+        self.cache.put_commit(hash, commit.clone());
+        Ok(commit)
     }
 
     fn check_commit_exists(&self, hash: &str) -> anyhow::Result<bool> {
         let mut stmt = self
             .sqlite_db
-            .prepare("SELECT hash FROM commits WHERE hash = ?1")
+            .prepare_cached("SELECT hash FROM commits WHERE hash = ?1")
             .context("Cannot create statement")?;
 
         let mut rows = stmt.query([hash]).context("Cannot query branch")?;
@@ -263,6 +605,35 @@ impl DB for Persistence {
         Ok(next.is_some())
     }
 
+    fn write_commit_signature(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        author_pubkey: &[u8],
+        signature: &[u8],
+    ) -> anyhow::Result<()> {
+        tx.execute(
+            "INSERT OR REPLACE INTO commit_signatures (commit_hash, author_pubkey, signature) VALUES (?1, ?2, ?3)",
+            (commit_hash, author_pubkey, signature),
+        )
+        .context("Cannot insert commit signature")?;
+
+        Ok(())
+    }
+
+    fn read_commit_signature(
+        &self,
+        commit_hash: &str,
+    ) -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.sqlite_db
+            .query_row(
+                "SELECT author_pubkey, signature FROM commit_signatures WHERE commit_hash = ?1",
+                [commit_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .context("Cannot read commit signature")
+    }
+
     fn read_ancestors_of_commit(
         &self,
         starting_from_hash: &str,
@@ -271,11 +642,12 @@ impl DB for Persistence {
             .sqlite_db
             .prepare(
                 "
-                WITH RECURSIVE ancestor_commits(hash, branch, message, prev_commit_hash, date) AS (
-                    SELECT hash, branch, message, prev_commit_hash, date FROM commits WHERE hash = ?1
-                    UNION ALL
-                    SELECT c.hash, c.branch, c.message, c.prev_commit_hash, c.date FROM commits c
-                    JOIN ancestor_commits a ON a.prev_commit_hash = c.hash
+                WITH RECURSIVE ancestor_commits(hash, branch, message, date) AS (
+                    SELECT hash, branch, message, date FROM commits WHERE hash = ?1
+                    UNION
+                    SELECT c.hash, c.branch, c.message, c.date FROM commits c
+                    JOIN commit_parents cp ON cp.parent_hash = c.hash
+                    JOIN ancestor_commits a ON cp.child_hash = a.hash
                 )
                 SELECT hash, branch, message FROM ancestor_commits ORDER BY date DESC;
                 ",
@@ -287,12 +659,45 @@ impl DB for Persistence {
             .context("Cannot read commits")?;
 
         let mut result: Vec<ShortCommitRecord> = vec![];
-        while let Ok(Some(data)) = rows.next() {
-            result.push(ShortCommitRecord {
-                hash: data.get(0).expect("cannot get hash"),
-                branch: data.get(1).expect("cannot get branch"),
-                message: data.get(2).expect("cannot read message"),
-            })
+        while let Some(data) = rows.next()? {
+            result.push(ShortCommitRecord::from_row(data)?)
+        }
+
+        Ok(result)
+    }
+
+    fn read_ancestors_page(
+        &self,
+        starting_from_hash: &str,
+        max_depth: i64,
+        before_date: Option<i64>,
+    ) -> anyhow::Result<Vec<ShortCommitRecord>> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare(
+                "
+                WITH RECURSIVE ancestor_commits(hash, branch, message, date, depth) AS (
+                    SELECT hash, branch, message, date, 0 FROM commits WHERE hash = ?1
+                    UNION
+                    SELECT c.hash, c.branch, c.message, c.date, a.depth + 1 FROM commits c
+                    JOIN commit_parents cp ON cp.parent_hash = c.hash
+                    JOIN ancestor_commits a ON cp.child_hash = a.hash
+                    WHERE a.depth + 1 < ?2
+                )
+                SELECT hash, branch, message FROM ancestor_commits
+                WHERE ?3 IS NULL OR date < ?3
+                ORDER BY date DESC;
+                ",
+            )
+            .context("Cannot prepare read commits query")?;
+
+        let mut rows = stmt
+            .query(rusqlite::params![starting_from_hash, max_depth, before_date])
+            .context("Cannot read commits")?;
+
+        let mut result: Vec<ShortCommitRecord> = vec![];
+        while let Some(data) = rows.next()? {
+            result.push(ShortCommitRecord::from_row(data)?)
         }
 
         Ok(result)
@@ -305,9 +710,10 @@ impl DB for Persistence {
                 "
                 WITH RECURSIVE descendant_commits(hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers) AS (
                     SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE hash = ?1
-                    UNION ALL
+                    UNION
                     SELECT c.hash, c.prev_commit_hash, c.project_id, c.branch, c.message, c.author, c.date, c.header, c.blocks_and_pointers FROM commits c
-                    JOIN descendant_commits a ON c.prev_commit_hash = a.hash
+                    JOIN commit_parents cp ON cp.child_hash = c.hash
+                    JOIN descendant_commits a ON cp.parent_hash = a.hash
                 )
                 SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM descendant_commits ORDER BY date ASC;
                 ",
@@ -318,28 +724,57 @@ impl DB for Persistence {
 
         let mut result: Vec<Commit> = vec![];
 
-        while let Ok(Some(data)) = rows.next() {
-            let hash: String = data
-                .get::<usize, String>(0)
-                .expect("No hash found in row")
-                .to_string();
-
-            result.push(Commit {
-                hash,
-                prev_commit_hash: data.get(1).expect("No prev_commit_hash found in row"),
-                project_id: data.get(2).expect("No project_id found in row"),
-                branch: data.get(3).expect("No branch found in row"),
-                message: data.get(4).expect("No message found in row"),
-                author: data.get(5).expect("No author found in row"),
-                date: data.get(6).expect("No date found in row"),
-                header: data.get(7).expect("No header found in row"),
-                blocks_and_pointers: data.get(8).expect("No blocks found in row"),
-            })
+        while let Some(data) = rows.next()? {
+            result.push(Commit::from_row(data)?)
         }
 
         Ok(result)
     }
 
+    fn read_commits_since(&self, since_date: i64) -> anyhow::Result<Vec<Commit>> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare(
+                "SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE date >= ?1",
+            )
+            .context("Cannot prepare read commits query")?;
+
+        let mut rows = stmt.query([since_date]).context("Cannot read commits")?;
+
+        let mut result: Vec<Commit> = vec![];
+
+        while let Some(data) = rows.next()? {
+            result.push(Commit::from_row(data)?)
+        }
+
+        Ok(result)
+    }
+
+    fn read_commits_for_branch(&self, branch_name: &str) -> anyhow::Result<Vec<Commit>> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare(
+                "SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE branch = ?1",
+            )
+            .context("Cannot prepare read commits query")?;
+
+        let mut rows = stmt.query([branch_name]).context("Cannot read commits")?;
+
+        let mut result: Vec<Commit> = vec![];
+
+        while let Some(data) = rows.next()? {
+            result.push(Commit::from_row(data)?)
+        }
+
+        Ok(result)
+    }
+
+    fn delete_commit(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<()> {
+        tx.execute("DELETE FROM commits WHERE hash = ?1", [hash])
+            .context("Cannot delete commit")?;
+        Ok(())
+    }
+
     fn read_current_branch_name(&self) -> anyhow::Result<String> {
         let current_branch_name = read_config_inner(&self.sqlite_db, &current_branch_name_key())?;
         if let Some(current_branch_name) = current_branch_name {
@@ -367,29 +802,34 @@ impl DB for Persistence {
 
         let mut result: Vec<String> = vec![];
 
-        while let Ok(Some(data)) = rows.next() {
-            let name = data.get(0).unwrap();
-
-            result.push(name);
+        while let Some(data) = rows.next()? {
+            result.push(data.get(0)?);
         }
 
         Ok(result)
     }
 
     fn read_branch_tip(&self, branch_name: &str) -> anyhow::Result<Option<String>> {
+        if let Some(cached) = self.cache.get_branch_tip(branch_name) {
+            return Ok(cached);
+        }
+
         let mut stmt = self
             .sqlite_db
-            .prepare("SELECT tip FROM branches WHERE name = ?1")?;
+            .prepare_cached("SELECT tip FROM branches WHERE name = ?1")?;
 
         let mut rows = stmt.query([branch_name])?;
 
         let row = rows.next()?;
 
-        if let Some(data) = row {
-            Ok(Some(data.get(0).unwrap()))
+        let tip = if let Some(data) = row {
+            Some(data.get(0).unwrap())
         } else {
-            Ok(None)
-        }
+            None
+        };
+
+        self.cache.put_branch_tip(branch_name, tip.clone());
+        Ok(tip)
     }
 
     fn write_branch_tip(
@@ -406,15 +846,24 @@ impl DB for Persistence {
     }
 
     fn read_current_commit_pointer(&self) -> anyhow::Result<String> {
-        read_config_inner(&self.sqlite_db, &current_latest_commit_key()).and_then(|v| {
-            if let Some(v) = v {
-                Ok(v)
-            } else {
-                bail!(DBError::Consistency(
-                    "Current commit pointer not set".to_owned(),
-                ))
-            }
-        })
+        if let Some(cached) = self.cache.get_current_commit_pointer() {
+            return Ok(cached);
+        }
+
+        let hash = read_config_inner(&self.sqlite_db, &current_latest_commit_key()).and_then(
+            |v| {
+                if let Some(v) = v {
+                    Ok(v)
+                } else {
+                    bail!(DBError::Consistency(
+                        "Current commit pointer not set".to_owned(),
+                    ))
+                }
+            },
+        )?;
+
+        self.cache.put_current_commit_pointer(hash.clone());
+        Ok(hash)
     }
 
     fn write_current_commit_pointer(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<()> {
@@ -422,6 +871,17 @@ impl DB for Persistence {
             .context("Cannot write latest commit hash")
     }
 
+    fn read_schema_version(&self) -> anyhow::Result<i64> {
+        let version = read_config_inner(&self.sqlite_db, &schema_version_key())
+            .context("Cannot read schema version")?;
+        Ok(version.and_then(|v| v.parse().ok()).unwrap_or(0))
+    }
+
+    fn write_schema_version(tx: &rusqlite::Transaction, version: i64) -> anyhow::Result<()> {
+        write_config_inner(tx, &schema_version_key(), &version.to_string())
+            .context("Cannot write schema version")
+    }
+
     fn execute_in_transaction<F>(&mut self, f: F) -> anyhow::Result<()>
     where
         F: FnOnce(&rusqlite::Transaction) -> anyhow::Result<()>,
@@ -433,7 +893,16 @@ impl DB for Persistence {
 
         f(&tx)?;
 
-        tx.commit().context("Cannot commit transaction")
+        tx.commit().context("Cannot commit transaction")?;
+
+        // `f` is an arbitrary closure made up of `Persistence::write_*` associated
+        // functions, which take `tx` but no `self` (so they can run inside this very
+        // closure without aliasing `&mut self` twice) -- that also means there's no
+        // way to tell from here which of `write_branch_tip`/`write_current_commit_pointer`
+        // a given call actually touched. Evict everything rather than track that.
+        self.cache.invalidate_all();
+
+        Ok(())
     }
 
     fn read_project_id(&self) -> anyhow::Result<String> {
@@ -491,6 +960,114 @@ impl DB for Persistence {
         write_config_inner(tx, &user_name_key(), name)
     }
 
+    fn read_encryption_salt(&self) -> anyhow::Result<Option<String>> {
+        read_config_inner(&self.sqlite_db, &encryption_salt_key())
+    }
+
+    fn write_encryption_salt(tx: &rusqlite::Transaction, salt_hex: &str) -> anyhow::Result<()> {
+        write_config_inner(tx, &encryption_salt_key(), salt_hex)
+    }
+
+    fn read_encryption_kdf_params(&self) -> anyhow::Result<Option<String>> {
+        read_config_inner(&self.sqlite_db, &encryption_kdf_params_key())
+    }
+
+    fn write_encryption_kdf_params(
+        tx: &rusqlite::Transaction,
+        kdf_params: &str,
+    ) -> anyhow::Result<()> {
+        write_config_inner(tx, &encryption_kdf_params_key(), kdf_params)
+    }
+
+    fn read_bisect_state(&self) -> anyhow::Result<Option<BisectState>> {
+        let candidates = read_config_inner(&self.sqlite_db, &bisect_candidates_key())?;
+        let low = read_config_inner(&self.sqlite_db, &bisect_low_key())?;
+        let high = read_config_inner(&self.sqlite_db, &bisect_high_key())?;
+
+        match (candidates, low, high) {
+            (Some(candidates), Some(low), Some(high)) => Ok(Some(BisectState {
+                candidates: candidates.split(',').map(str::to_owned).collect(),
+                low: low.parse().context("Cannot parse bisect low bound")?,
+                high: high.parse().context("Cannot parse bisect high bound")?,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn write_bisect_state(tx: &rusqlite::Transaction, state: &BisectState) -> anyhow::Result<()> {
+        write_config_inner(tx, &bisect_candidates_key(), &state.candidates.join(","))?;
+        write_config_inner(tx, &bisect_low_key(), &state.low.to_string())?;
+        write_config_inner(tx, &bisect_high_key(), &state.high.to_string())
+    }
+
+    fn clear_bisect_state(tx: &rusqlite::Transaction) -> anyhow::Result<()> {
+        tx.execute(
+            "DELETE FROM config WHERE key IN (?1, ?2, ?3)",
+            [bisect_candidates_key(), bisect_low_key(), bisect_high_key()],
+        )
+        .context("Cannot clear bisect state")?;
+
+        Ok(())
+    }
+
+    fn read_merge_parent(&self, commit_hash: &str) -> anyhow::Result<Option<String>> {
+        self.sqlite_db
+            .query_row(
+                "SELECT second_parent_hash FROM merge_parents WHERE commit_hash = ?1",
+                [commit_hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Cannot read merge parent")
+    }
+
+    fn write_merge_parent(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        second_parent_hash: &str,
+    ) -> anyhow::Result<()> {
+        tx.execute(
+            "INSERT OR REPLACE INTO merge_parents (commit_hash, second_parent_hash) VALUES (?1, ?2)",
+            [commit_hash, second_parent_hash],
+        )
+        .context("Cannot write merge parent")?;
+
+        Ok(())
+    }
+
+    fn read_parents_of_commit(&self, commit_hash: &str) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self
+            .sqlite_db
+            .prepare(
+                "SELECT parent_hash FROM commit_parents WHERE child_hash = ?1 ORDER BY ordinal ASC",
+            )
+            .context("Cannot prepare read commit parents query")?;
+
+        let parents = stmt
+            .query_map([commit_hash], |row| row.get(0))
+            .context("Cannot read commit parents")?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .context("Cannot read commit parents")?;
+
+        Ok(parents)
+    }
+
+    fn write_commit_parents(
+        tx: &rusqlite::Transaction,
+        child_hash: &str,
+        parent_hashes: &[String],
+    ) -> anyhow::Result<()> {
+        for (ordinal, parent_hash) in parent_hashes.iter().enumerate() {
+            tx.execute(
+                "INSERT OR REPLACE INTO commit_parents (child_hash, parent_hash, ordinal) VALUES (?1, ?2, ?3)",
+                rusqlite::params![child_hash, parent_hash, ordinal as i64],
+            )
+            .context("Cannot write commit parent")?;
+        }
+
+        Ok(())
+    }
+
     fn read_last_modification_time(&self) -> anyhow::Result<Option<i64>> {
         let raw = read_config_inner(&self.sqlite_db, &last_mod_time_key())?;
         if let Some(raw) = raw {
@@ -510,3 +1087,36 @@ impl DB for Persistence {
         write_config_inner(tx, &last_mod_time_key(), &last_mod_time.to_string())
     }
 }
+
+impl Persistence {
+    /// Brings a just-opened store forward to [`crate::db::migrations::CURRENT_SCHEMA_VERSION`],
+    /// running every migration whose `version` is above whatever `schema_version`
+    /// currently reports. Each migration runs in its own `execute_in_transaction`
+    /// call that also stamps the new version, so a crash partway through an upgrade
+    /// leaves the store at the last successfully-applied version -- the next `open`
+    /// simply resumes from there instead of re-running completed steps.
+    fn run_pending_migrations(&mut self) -> anyhow::Result<()> {
+        let from_version = self.read_schema_version()?;
+
+        let highest_known_version = MIGRATIONS
+            .iter()
+            .map(|m| m.version)
+            .max()
+            .unwrap_or_default();
+        if from_version > highest_known_version {
+            bail!(DBError::Fundamental(format!(
+                "Database is at schema version {}, but this build only knows migrations up to version {} -- it was created by a newer client",
+                from_version, highest_known_version
+            )));
+        }
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+            self.execute_in_transaction(|tx| {
+                (migration.run)(tx)?;
+                Self::write_schema_version(tx, migration.version)
+            })?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use rusqlite::Transaction;
+
+/// One forward-only schema change, applied inside `execute_in_transaction`.
+/// Migrations run in ascending `version` order, starting just above whatever version
+/// the store currently reports.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub run: fn(&Transaction) -> anyhow::Result<()>,
+}
+
+/// The schema version a fresh `init_db` stamps, and the version `migrate` brings
+/// older stores forward to. Bump this and append a [`Migration`] whenever
+/// `Persistence::open`'s `CREATE TABLE` statements change in a way existing stores
+/// need to catch up on.
+pub const CURRENT_SCHEMA_VERSION: i64 = 4;
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Baseline schema: commits, branches, blocks, config",
+        run: |_tx| Ok(()),
+    },
+    Migration {
+        version: 2,
+        description: "Add merge_parents table for second-parent merge commits",
+        run: |tx| {
+            tx.execute(
+                "CREATE TABLE IF NOT EXISTS merge_parents (
+                    commit_hash TEXT PRIMARY KEY,
+                    second_parent_hash TEXT
+                )",
+                [],
+            )?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 3,
+        description: "Add commit_signatures table for optional commit signing",
+        run: |tx| {
+            tx.execute(
+                "CREATE TABLE IF NOT EXISTS commit_signatures (
+                    commit_hash TEXT PRIMARY KEY,
+                    author_pubkey BLOB,
+                    signature BLOB
+                )",
+                [],
+            )?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 4,
+        description: "Backfill commit_parents from prev_commit_hash for stores that predate DAG support",
+        run: |tx| {
+            // `open_inner` already creates this table unconditionally (same pattern as
+            // `merge_parents`'s migration 2, below its own unconditional create) -- repeated
+            // here so this migration is self-sufficient for a store opened by a build old
+            // enough to still create it only through migrations.
+            tx.execute(
+                "CREATE TABLE IF NOT EXISTS commit_parents (
+                    child_hash TEXT,
+                    parent_hash TEXT,
+                    ordinal INTEGER,
+                    PRIMARY KEY (child_hash, ordinal)
+                )",
+                [],
+            )?;
+
+            // Every commit written before this migration existed only ever had one
+            // parent, recorded as `prev_commit_hash` -- including the first commit,
+            // whose `prev_commit_hash` is the `"initial"` sentinel rather than a real
+            // hash, which readers of `commit_parents` already filter out. `INSERT OR
+            // IGNORE` leaves alone any row a newer `write_commit` already populated.
+            tx.execute(
+                "INSERT OR IGNORE INTO commit_parents (child_hash, parent_hash, ordinal)
+                 SELECT hash, prev_commit_hash, 0 FROM commits",
+                [],
+            )?;
+
+            // Historic merge commits recorded their second parent in `merge_parents`
+            // instead, which predates `commit_parents` entirely -- without this, a
+            // pre-existing merge loses its second-parent edge the moment a store
+            // upgrades, since `branch_history_command` and the ancestor/descendant
+            // queries now read exclusively from `commit_parents`.
+            tx.execute(
+                "INSERT OR IGNORE INTO commit_parents (child_hash, parent_hash, ordinal)
+                 SELECT commit_hash, second_parent_hash, 1 FROM merge_parents
+                 WHERE second_parent_hash IS NOT NULL",
+                [],
+            )?;
+
+            Ok(())
+        },
+    },
+];
+
+#[cfg(test)]
+mod test {
+    use tempfile::NamedTempFile;
+
+    use crate::db::db_ops::{Persistence, DB};
+
+    #[test]
+    fn test_migration_4_backfills_both_parents_of_a_pre_existing_merge() {
+        let tmp_file = NamedTempFile::new().expect("Cannot create temp dir");
+        let tmp_path = tmp_file.path().to_str().expect("Cannot get temp file path");
+
+        // Hand-build a store in the shape a merge commit was written in before
+        // `commit_parents` existed: the first parent only as `prev_commit_hash` on
+        // the commit row, the second parent in `merge_parents`.
+        {
+            let conn = rusqlite::Connection::open(tmp_path).expect("Cannot create fixture db");
+            conn.execute(
+                "CREATE TABLE commits (
+                    hash TEXT PRIMARY KEY,
+                    prev_commit_hash TEXT,
+                    project_id TEXT,
+                    branch TEXT,
+                    message TEXT,
+                    author TEXT,
+                    date INTEGER,
+                    header BLOB,
+                    blocks_and_pointers BLOB
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute("CREATE TABLE branches (name TEXT PRIMARY KEY, tip TEXT)", [])
+                .unwrap();
+            conn.execute("CREATE TABLE blocks (key TEXT PRIMARY KEY, value BLOB)", [])
+                .unwrap();
+            conn.execute("CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT)", [])
+                .unwrap();
+            conn.execute(
+                "CREATE TABLE merge_parents (commit_hash TEXT PRIMARY KEY, second_parent_hash TEXT)",
+                [],
+            )
+            .unwrap();
+
+            conn.execute(
+                "INSERT INTO commits (hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers)
+                 VALUES ('merge-hash', 'first-parent', 'old-project', 'main', 'Merge branch', 'Anon', 0, x'', x'')",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO merge_parents (commit_hash, second_parent_hash) VALUES ('merge-hash', 'second-parent')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Persistence::open(tmp_path).expect("Cannot open old-format fixture db");
+
+        let parents = db
+            .read_parents_of_commit("merge-hash")
+            .expect("Cannot read commit parents");
+
+        assert_eq!(
+            parents,
+            vec!["first-parent".to_owned(), "second-parent".to_owned()]
+        );
+    }
+}
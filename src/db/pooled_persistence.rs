@@ -0,0 +1,390 @@
+use std::sync::Mutex;
+
+use anyhow::{bail, Context};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::OptionalExtension;
+
+use super::db_ops::{
+    BisectState, DBError, FromRow, Persistence, ShortCommitRecord, DB, SQLITE_MAX_VARIABLE_NUMBER,
+};
+use super::structs::{BlockRecord, Commit};
+
+/// Alternative to `Persistence` for callers that want concurrent reads: a pool of
+/// read connections sits alongside a single writer connection (itself a plain
+/// `Persistence`, reused for everything `execute_in_transaction` does and for the
+/// less hot-path reads), all pointed at the same file with WAL journaling enabled.
+/// WAL lets SQLite serve readers from the last-committed snapshot without blocking
+/// on an in-progress write, so the methods a UI tends to poll while a checkpoint is
+/// being written -- `read_ancestors_of_commit`, `read_all_branches`, `read_commit`,
+/// `read_blocks` -- check out a pooled connection instead of going through the
+/// writer mutex every other method here still uses.
+pub struct PooledPersistence {
+    readers: Pool<SqliteConnectionManager>,
+    writer: Mutex<Persistence>,
+}
+
+impl PooledPersistence {
+    fn reader(&self) -> anyhow::Result<r2d2::PooledConnection<SqliteConnectionManager>> {
+        self.readers
+            .get()
+            .context("Cannot check out pooled read connection")
+    }
+
+    fn writer(&self) -> std::sync::MutexGuard<'_, Persistence> {
+        self.writer
+            .lock()
+            .expect("writer connection mutex was poisoned by a panicking holder")
+    }
+
+    fn open_with(readers: Pool<SqliteConnectionManager>, writer: Persistence) -> anyhow::Result<Self> {
+        // WAL mode is sticky per-file once set (SQLite stores it in the database
+        // header), so flipping it through any one connection is enough for every
+        // other connection against this file -- including the writer -- to see it.
+        readers
+            .get()
+            .context("Cannot check out pooled read connection")?
+            .pragma_update(None, "journal_mode", "WAL")
+            .context("Cannot enable WAL mode")?;
+
+        Ok(PooledPersistence {
+            readers,
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl DB for PooledPersistence {
+    fn open(path: &str) -> anyhow::Result<Self> {
+        let readers = Pool::new(SqliteConnectionManager::file(path))
+            .context("Cannot create pooled read connections")?;
+        let writer = Persistence::open(path)?;
+        Self::open_with(readers, writer)
+    }
+
+    fn open_encrypted(path: &str, passphrase: &str) -> anyhow::Result<Self> {
+        // Unlike WAL mode, SQLCipher's key isn't recoverable from the (still
+        // encrypted) file header, so every connection the pool ever creates needs to
+        // be keyed individually -- hence `with_init` rather than keying one
+        // connection up front the way `open` enables WAL.
+        let writer = Persistence::open_encrypted(path, passphrase)?;
+
+        let keyed_passphrase = passphrase.to_owned();
+        let manager = SqliteConnectionManager::file(path)
+            .with_init(move |conn| conn.pragma_update(None, "key", &keyed_passphrase));
+        let readers = Pool::new(manager).context("Cannot create pooled read connections")?;
+
+        Self::open_with(readers, writer)
+    }
+
+    fn rekey(&self, new_passphrase: &str) -> anyhow::Result<()> {
+        self.writer().rekey(new_passphrase)
+    }
+
+    fn read_is_db_encrypted(&self) -> anyhow::Result<bool> {
+        self.writer().read_is_db_encrypted()
+    }
+
+    fn write_is_db_encrypted(tx: &rusqlite::Transaction) -> anyhow::Result<()> {
+        Persistence::write_is_db_encrypted(tx)
+    }
+
+    fn write_blocks(tx: &rusqlite::Transaction, blocks: &[BlockRecord]) -> anyhow::Result<()> {
+        Persistence::write_blocks(tx, blocks)
+    }
+
+    fn read_blocks(&self, hashes: Vec<String>) -> anyhow::Result<Vec<BlockRecord>> {
+        let conn = self.reader()?;
+
+        let mut by_hash: std::collections::HashMap<String, Vec<u8>> =
+            std::collections::HashMap::with_capacity(hashes.len());
+
+        for chunk in hashes.chunks(SQLITE_MAX_VARIABLE_NUMBER) {
+            let placeholders = chunk.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+            let query = format!("SELECT key, value FROM blocks WHERE key IN ({placeholders})");
+
+            let mut stmt = conn.prepare_cached(&query)?;
+            let params = rusqlite::params_from_iter(chunk.iter());
+            let mut rows = stmt.query(params)?;
+
+            while let Some(row) = rows.next()? {
+                by_hash.insert(row.get(0)?, row.get(1)?);
+            }
+        }
+
+        let missing: Vec<&String> = hashes.iter().filter(|h| !by_hash.contains_key(*h)).collect();
+        if !missing.is_empty() {
+            bail!(DBError::Consistency(format!(
+                "No block found for hashes: {:?}",
+                missing
+            )));
+        }
+
+        Ok(hashes
+            .into_iter()
+            .map(|hash| {
+                let data = by_hash.get(&hash).expect("checked missing above").clone();
+                BlockRecord { hash, data }
+            })
+            .collect())
+    }
+
+    fn check_block_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        self.writer().check_block_exists(hash)
+    }
+
+    fn read_all_block_hashes(&self) -> anyhow::Result<Vec<String>> {
+        self.writer().read_all_block_hashes()
+    }
+
+    fn overwrite_block(tx: &rusqlite::Transaction, block: &BlockRecord) -> anyhow::Result<()> {
+        Persistence::overwrite_block(tx, block)
+    }
+
+    fn delete_blocks(tx: &rusqlite::Transaction, hashes: &[String]) -> anyhow::Result<()> {
+        Persistence::delete_blocks(tx, hashes)
+    }
+
+    fn write_commit(tx: &rusqlite::Transaction, commit: Commit) -> anyhow::Result<()> {
+        Persistence::write_commit(tx, commit)
+    }
+
+    fn read_commit(&self, hash: &str) -> anyhow::Result<Option<Commit>> {
+        let conn = self.reader()?;
+        conn.query_row(
+            "SELECT hash, prev_commit_hash, project_id, branch, message, author, date, header, blocks_and_pointers FROM commits WHERE hash = ?1",
+            [hash],
+            |row| Commit::from_row(row),
+        )
+        .optional()
+        .context("Cannot read commit")
+    }
+
+    fn check_commit_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        self.writer().check_commit_exists(hash)
+    }
+
+    fn write_commit_signature(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        author_pubkey: &[u8],
+        signature: &[u8],
+    ) -> anyhow::Result<()> {
+        Persistence::write_commit_signature(tx, commit_hash, author_pubkey, signature)
+    }
+
+    fn read_commit_signature(
+        &self,
+        commit_hash: &str,
+    ) -> anyhow::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.writer().read_commit_signature(commit_hash)
+    }
+
+    fn read_ancestors_of_commit(
+        &self,
+        starting_from_hash: &str,
+    ) -> anyhow::Result<Vec<ShortCommitRecord>> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare_cached(
+                "
+                WITH RECURSIVE ancestor_commits(hash, branch, message, date) AS (
+                    SELECT hash, branch, message, date FROM commits WHERE hash = ?1
+                    UNION
+                    SELECT c.hash, c.branch, c.message, c.date FROM commits c
+                    JOIN commit_parents cp ON cp.parent_hash = c.hash
+                    JOIN ancestor_commits a ON cp.child_hash = a.hash
+                )
+                SELECT hash, branch, message FROM ancestor_commits ORDER BY date DESC;
+                ",
+            )
+            .context("Cannot prepare read commits query")?;
+
+        let mut rows = stmt
+            .query([starting_from_hash])
+            .context("Cannot read commits")?;
+
+        let mut result: Vec<ShortCommitRecord> = vec![];
+        while let Some(data) = rows.next()? {
+            result.push(ShortCommitRecord::from_row(data)?)
+        }
+
+        Ok(result)
+    }
+
+    fn read_ancestors_page(
+        &self,
+        starting_from_hash: &str,
+        max_depth: i64,
+        before_date: Option<i64>,
+    ) -> anyhow::Result<Vec<ShortCommitRecord>> {
+        self.writer()
+            .read_ancestors_page(starting_from_hash, max_depth, before_date)
+    }
+
+    fn read_descendants_of_commit(&self, hash: &str) -> anyhow::Result<Vec<Commit>> {
+        self.writer().read_descendants_of_commit(hash)
+    }
+
+    fn read_commits_since(&self, since_date: i64) -> anyhow::Result<Vec<Commit>> {
+        self.writer().read_commits_since(since_date)
+    }
+
+    fn read_commits_for_branch(&self, branch_name: &str) -> anyhow::Result<Vec<Commit>> {
+        self.writer().read_commits_for_branch(branch_name)
+    }
+
+    fn delete_commit(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<()> {
+        Persistence::delete_commit(tx, hash)
+    }
+
+    fn read_current_branch_name(&self) -> anyhow::Result<String> {
+        self.writer().read_current_branch_name()
+    }
+
+    fn write_current_branch_name(
+        tx: &rusqlite::Transaction,
+        brach_name: &str,
+    ) -> anyhow::Result<()> {
+        Persistence::write_current_branch_name(tx, brach_name)
+    }
+
+    fn read_current_commit_pointer(&self) -> anyhow::Result<String> {
+        self.writer().read_current_commit_pointer()
+    }
+
+    fn write_current_commit_pointer(tx: &rusqlite::Transaction, hash: &str) -> anyhow::Result<()> {
+        Persistence::write_current_commit_pointer(tx, hash)
+    }
+
+    fn read_all_branches(&self) -> anyhow::Result<Vec<String>> {
+        let conn = self.reader()?;
+        let mut stmt = conn
+            .prepare_cached("SELECT name FROM branches")
+            .context("Cannot query branches")?;
+        let mut rows = stmt.query([]).context("Cannot query branches")?;
+
+        let mut result: Vec<String> = vec![];
+        while let Some(data) = rows.next()? {
+            result.push(data.get(0)?);
+        }
+
+        Ok(result)
+    }
+
+    fn read_branch_tip(&self, branch_name: &str) -> anyhow::Result<Option<String>> {
+        self.writer().read_branch_tip(branch_name)
+    }
+
+    fn write_branch_tip(
+        tx: &rusqlite::Transaction,
+        brach_name: &str,
+        tip: &str,
+    ) -> anyhow::Result<()> {
+        Persistence::write_branch_tip(tx, brach_name, tip)
+    }
+
+    fn read_project_id(&self) -> anyhow::Result<String> {
+        self.writer().read_project_id()
+    }
+
+    fn write_project_id(tx: &rusqlite::Transaction, last_mod_time: &str) -> anyhow::Result<()> {
+        Persistence::write_project_id(tx, last_mod_time)
+    }
+
+    fn read_last_modification_time(&self) -> anyhow::Result<Option<i64>> {
+        self.writer().read_last_modification_time()
+    }
+
+    fn write_last_modifiction_time(
+        tx: &rusqlite::Transaction,
+        last_mod_time: i64,
+    ) -> anyhow::Result<()> {
+        Persistence::write_last_modifiction_time(tx, last_mod_time)
+    }
+
+    fn read_name(&self) -> anyhow::Result<Option<String>> {
+        self.writer().read_name()
+    }
+
+    fn write_name(tx: &rusqlite::Transaction, name: &str) -> anyhow::Result<()> {
+        Persistence::write_name(tx, name)
+    }
+
+    fn read_encryption_salt(&self) -> anyhow::Result<Option<String>> {
+        self.writer().read_encryption_salt()
+    }
+
+    fn write_encryption_salt(tx: &rusqlite::Transaction, salt_hex: &str) -> anyhow::Result<()> {
+        Persistence::write_encryption_salt(tx, salt_hex)
+    }
+
+    fn read_encryption_kdf_params(&self) -> anyhow::Result<Option<String>> {
+        self.writer().read_encryption_kdf_params()
+    }
+
+    fn write_encryption_kdf_params(
+        tx: &rusqlite::Transaction,
+        kdf_params: &str,
+    ) -> anyhow::Result<()> {
+        Persistence::write_encryption_kdf_params(tx, kdf_params)
+    }
+
+    fn read_bisect_state(&self) -> anyhow::Result<Option<BisectState>> {
+        self.writer().read_bisect_state()
+    }
+
+    fn write_bisect_state(tx: &rusqlite::Transaction, state: &BisectState) -> anyhow::Result<()> {
+        Persistence::write_bisect_state(tx, state)
+    }
+
+    fn clear_bisect_state(tx: &rusqlite::Transaction) -> anyhow::Result<()> {
+        Persistence::clear_bisect_state(tx)
+    }
+
+    fn read_merge_parent(&self, commit_hash: &str) -> anyhow::Result<Option<String>> {
+        self.writer().read_merge_parent(commit_hash)
+    }
+
+    fn write_merge_parent(
+        tx: &rusqlite::Transaction,
+        commit_hash: &str,
+        second_parent_hash: &str,
+    ) -> anyhow::Result<()> {
+        Persistence::write_merge_parent(tx, commit_hash, second_parent_hash)
+    }
+
+    fn read_parents_of_commit(&self, commit_hash: &str) -> anyhow::Result<Vec<String>> {
+        self.writer().read_parents_of_commit(commit_hash)
+    }
+
+    fn write_commit_parents(
+        tx: &rusqlite::Transaction,
+        child_hash: &str,
+        parent_hashes: &[String],
+    ) -> anyhow::Result<()> {
+        Persistence::write_commit_parents(tx, child_hash, parent_hashes)
+    }
+
+    fn delete_branch_with_commits(
+        tx: &rusqlite::Transaction,
+        branch_name: &str,
+    ) -> anyhow::Result<()> {
+        Persistence::delete_branch_with_commits(tx, branch_name)
+    }
+
+    fn read_schema_version(&self) -> anyhow::Result<i64> {
+        self.writer().read_schema_version()
+    }
+
+    fn write_schema_version(tx: &rusqlite::Transaction, version: i64) -> anyhow::Result<()> {
+        Persistence::write_schema_version(tx, version)
+    }
+
+    fn execute_in_transaction<F>(&mut self, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&rusqlite::Transaction) -> anyhow::Result<()>,
+    {
+        self.writer().execute_in_transaction(f)
+    }
+}
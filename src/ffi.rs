@@ -3,12 +3,23 @@ use std::{error::Error, fmt::Display};
 use serde::Serialize;
 use serde_json::{Map, Value};
 
-use crate::api::{
-    blend_file_from_timeline_command, create_new_checkpoint_command::create_new_checkpoint,
-    delete_branch, get_current_branch::get_current_branch, get_current_commit::get_current_commit,
-    init_command::init_db, list_branches_command::list_braches,
-    log_checkpoints_command::list_checkpoints, new_branch_command::create_new_branch,
-    restore_command, switch_command::switch_branches,
+use crate::{
+    api::{
+        bisect_command::{start_bisect, step_bisect, BisectStep, BisectVerdict},
+        blend_file_from_timeline_command,
+        branch_history_command::{branch_history, CommitNode},
+        common::resolve_encryption_key,
+        create_new_checkpoint_command::create_new_checkpoint, delete_branch,
+        diff_checkpoints_command::diff_checkpoints,
+        gc_command::gc,
+        migrate_command::migrate,
+        get_current_branch::get_current_branch, get_current_commit::get_current_commit,
+        init_command::init_db, list_branches_command::list_braches,
+        log_checkpoints_command::list_checkpoints,
+        merge_branch_command::{merge_branch, MergeConflict},
+        new_branch_command::create_new_branch, restore_command, switch_command::switch_branches,
+    },
+    db::db_ops::{Persistence, DB},
 };
 
 #[derive(Serialize)]
@@ -53,6 +64,106 @@ pub struct DeleteBranchResponse {
     pub branches: Vec<String>,
 }
 
+#[derive(Serialize)]
+pub struct MergeConflictResponse {
+    pub block_address: u64,
+    pub base_hash: Option<String>,
+    pub ours_hash: String,
+    pub theirs_hash: String,
+}
+
+impl From<MergeConflict> for MergeConflictResponse {
+    fn from(conflict: MergeConflict) -> Self {
+        MergeConflictResponse {
+            block_address: conflict.block_address,
+            base_hash: conflict.base_hash,
+            ours_hash: conflict.ours_hash,
+            theirs_hash: conflict.theirs_hash,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MergeBranchResponse {
+    pub merged_commit_hash: String,
+    pub conflicts: Vec<MergeConflictResponse>,
+}
+
+#[derive(Serialize)]
+pub struct CommitNodeResponse {
+    pub hash: String,
+    pub message: String,
+    pub author: String,
+    pub date: u64,
+    pub branch: String,
+    pub parents: Vec<String>,
+}
+
+impl From<CommitNode> for CommitNodeResponse {
+    fn from(node: CommitNode) -> Self {
+        CommitNodeResponse {
+            hash: node.hash,
+            message: node.message,
+            author: node.author,
+            date: node.date,
+            branch: node.branch,
+            parents: node.parents,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BranchHistoryResponse {
+    pub commits: Vec<CommitNodeResponse>,
+}
+
+#[derive(Serialize)]
+pub struct GcResponse {
+    pub blocks_removed: usize,
+    pub bytes_reclaimed: usize,
+}
+
+#[derive(Serialize)]
+pub struct DiffCheckpointsResponse {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub common_count: usize,
+    pub approx_changed_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct MigrateResponse {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub applied: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BisectStepResponse {
+    pub checkpoint_hash: Option<String>,
+    pub first_bad_checkpoint: Option<String>,
+    pub done: bool,
+}
+
+impl From<BisectStep> for BisectStepResponse {
+    fn from(step: BisectStep) -> Self {
+        match step {
+            BisectStep::InProgress { checkpoint_hash } => BisectStepResponse {
+                checkpoint_hash: Some(checkpoint_hash),
+                first_bad_checkpoint: None,
+                done: false,
+            },
+            BisectStep::Done {
+                first_bad_checkpoint,
+            } => BisectStepResponse {
+                checkpoint_hash: None,
+                first_bad_checkpoint: Some(first_bad_checkpoint),
+                done: true,
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FFIError {
     MalformedMessage(String),
@@ -75,13 +186,17 @@ impl Display for FFIError {
 struct DBPath<'a>(&'a str);
 struct PathToBlend<'a>(&'a str);
 
-fn connect_command(db_path: DBPath, path_to_blend: PathToBlend) -> anyhow::Result<ConnectResponse> {
+fn connect_command(
+    db_path: DBPath,
+    path_to_blend: PathToBlend,
+    passphrase: Option<&str>,
+) -> anyhow::Result<ConnectResponse> {
     let exists = std::path::Path::new(&db_path.0).exists();
 
     if !exists {
         let project_id = uuid::Uuid::new_v4().to_string();
 
-        init_db(db_path.0, &project_id, path_to_blend.0)?;
+        init_db(db_path.0, &project_id, path_to_blend.0, passphrase)?;
     }
 
     let branches = list_braches(db_path.0)?;
@@ -102,8 +217,9 @@ fn create_checkpoint(
     db_path: DBPath,
     path_to_blend: PathToBlend,
     message: &str,
+    passphrase: Option<&str>,
 ) -> anyhow::Result<CreateCheckpointResponse> {
-    create_new_checkpoint(path_to_blend.0, db_path.0, Some(message.to_string()))?;
+    create_new_checkpoint(path_to_blend.0, db_path.0, Some(message.to_string()), passphrase)?;
 
     let current_branch_name = get_current_branch(db_path.0)?;
     let checkpoints_on_this_branch = list_checkpoints(db_path.0, &current_branch_name)
@@ -120,8 +236,12 @@ fn restore_checkpoint(
     db_path: DBPath,
     path_to_blend: PathToBlend,
     hash: &str,
+    passphrase: Option<&str>,
 ) -> anyhow::Result<RestoreCheckpointResponse> {
-    restore_command::restore_checkpoint(path_to_blend.0, db_path.0, hash)?;
+    let mut conn = Persistence::open(db_path.0)?;
+    let encryption_key = resolve_encryption_key(&mut conn, passphrase)?;
+
+    restore_command::restore_checkpoint(path_to_blend.0, db_path.0, hash, encryption_key.as_ref())?;
     let current_checkpoint_hash = get_current_commit(db_path.0)?;
     Ok(RestoreCheckpointResponse {
         current_checkpoint_hash,
@@ -146,8 +266,9 @@ fn switch_to_branch(
     db_path: DBPath,
     path_to_blend: PathToBlend,
     branch_name: &str,
+    passphrase: Option<&str>,
 ) -> anyhow::Result<SwitchBranchResponse> {
-    switch_branches(db_path.0, branch_name, path_to_blend.0)?;
+    switch_branches(db_path.0, branch_name, path_to_blend.0, passphrase)?;
     let current_branch_name = get_current_branch(db_path.0)?;
     let checkpoints_on_this_branch = list_checkpoints(db_path.0, &current_branch_name)
         .map(|commits| commits.into_iter().map(|c| (c.hash, c.message)).collect())?;
@@ -160,8 +281,12 @@ fn switch_to_branch(
     })
 }
 
-fn blend_file_from_timeline(db_path: DBPath) -> anyhow::Result<BlendFileFromTimelineResponse> {
-    let restored_file_path = blend_file_from_timeline_command::blend_file_from_timeline(db_path.0)?;
+fn blend_file_from_timeline(
+    db_path: DBPath,
+    passphrase: Option<&str>,
+) -> anyhow::Result<BlendFileFromTimelineResponse> {
+    let restored_file_path =
+        blend_file_from_timeline_command::blend_file_from_timeline(db_path.0, passphrase)?;
     Ok(BlendFileFromTimelineResponse { restored_file_path })
 }
 
@@ -171,6 +296,108 @@ fn delete_branch(db_path: DBPath, branch_name: &str) -> anyhow::Result<DeleteBra
     Ok(DeleteBranchResponse { branches })
 }
 
+fn merge_branches(
+    db_path: DBPath,
+    source_branch: &str,
+    target_branch: &str,
+) -> anyhow::Result<MergeBranchResponse> {
+    let result = merge_branch(db_path.0, source_branch, target_branch)?;
+    Ok(MergeBranchResponse {
+        merged_commit_hash: result.merged_commit_hash,
+        conflicts: result.conflicts.into_iter().map(Into::into).collect(),
+    })
+}
+
+fn run_gc(db_path: DBPath, keep_newer_than: Option<&str>) -> anyhow::Result<GcResponse> {
+    let keep_newer_than = keep_newer_than
+        .map(|value| {
+            value
+                .parse::<i64>()
+                .map_err(|_| FFIError::MalformedMessage("keep_newer_than is not an integer".to_string()))
+        })
+        .transpose()?;
+
+    let report = gc(db_path.0, keep_newer_than)?;
+    Ok(GcResponse {
+        blocks_removed: report.blocks_removed,
+        bytes_reclaimed: report.bytes_reclaimed,
+    })
+}
+
+fn run_diff_checkpoints(
+    db_path: DBPath,
+    from_hash: &str,
+    to_hash: &str,
+) -> anyhow::Result<DiffCheckpointsResponse> {
+    let report = diff_checkpoints(db_path.0, from_hash, to_hash)?;
+    Ok(DiffCheckpointsResponse {
+        added: report.added,
+        removed: report.removed,
+        common_count: report.common_count,
+        approx_changed_bytes: report.approx_changed_bytes,
+    })
+}
+
+fn run_migrate(db_path: DBPath) -> anyhow::Result<MigrateResponse> {
+    let report = migrate(db_path.0)?;
+    Ok(MigrateResponse {
+        from_version: report.from_version,
+        to_version: report.to_version,
+        applied: report.applied,
+    })
+}
+
+fn bisect_start(
+    db_path: DBPath,
+    path_to_blend: PathToBlend,
+    good_hash: &str,
+    bad_hash: &str,
+    passphrase: Option<&str>,
+) -> anyhow::Result<BisectStepResponse> {
+    let mut conn = Persistence::open(db_path.0)?;
+    let encryption_key = resolve_encryption_key(&mut conn, passphrase)?;
+
+    let step = start_bisect(
+        db_path.0,
+        path_to_blend.0,
+        good_hash,
+        bad_hash,
+        encryption_key.as_ref(),
+    )?;
+    Ok(step.into())
+}
+
+fn bisect_step(
+    db_path: DBPath,
+    path_to_blend: PathToBlend,
+    verdict: &str,
+    passphrase: Option<&str>,
+) -> anyhow::Result<BisectStepResponse> {
+    let verdict = match verdict {
+        "good" => BisectVerdict::Good,
+        "bad" => BisectVerdict::Bad,
+        other => {
+            return Err(
+                FFIError::MalformedMessage(format!("Unknown bisect verdict: {}", other)).into(),
+            )
+        }
+    };
+
+    let mut conn = Persistence::open(db_path.0)?;
+    let encryption_key = resolve_encryption_key(&mut conn, passphrase)?;
+
+    let step = step_bisect(db_path.0, path_to_blend.0, verdict, encryption_key.as_ref())?;
+    Ok(step.into())
+}
+
+fn get_branch_history(db_path: DBPath) -> anyhow::Result<BranchHistoryResponse> {
+    let commits = branch_history(db_path.0)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(BranchHistoryResponse { commits })
+}
+
 type JsonObject = Map<String, Value>;
 
 fn get_string_value<'a>(value: &'a JsonObject, key: &'a str) -> anyhow::Result<&'a str> {
@@ -180,6 +407,10 @@ fn get_string_value<'a>(value: &'a JsonObject, key: &'a str) -> anyhow::Result<&
         .ok_or(FFIError::MalformedMessage(format!("{} not in object", key)).into())
 }
 
+fn get_optional_string_value<'a>(value: &'a JsonObject, key: &'a str) -> Option<&'a str> {
+    value.get(key).and_then(|c| c.as_str())
+}
+
 pub fn error_json(error: FFIError) -> Value {
     let mut object = serde_json::Map::new();
     object.insert(
@@ -199,8 +430,10 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
         "connect" => {
             let db_path = get_string_value(value, "db_path")?;
             let path_to_blend = get_string_value(value, "path_to_blend")?;
+            let passphrase = get_optional_string_value(value, "passphrase");
 
-            let result = connect_command(DBPath(db_path), PathToBlend(path_to_blend))?;
+            let result =
+                connect_command(DBPath(db_path), PathToBlend(path_to_blend), passphrase)?;
             let s = serde_json::to_string(&result)?;
             Ok(s)
         }
@@ -209,8 +442,14 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
             let db_path = get_string_value(value, "db_path")?;
             let path_to_blend = get_string_value(value, "path_to_blend")?;
             let message = get_string_value(value, "message")?;
-
-            let result = create_checkpoint(DBPath(db_path), PathToBlend(path_to_blend), message)?;
+            let passphrase = get_optional_string_value(value, "passphrase");
+
+            let result = create_checkpoint(
+                DBPath(db_path),
+                PathToBlend(path_to_blend),
+                message,
+                passphrase,
+            )?;
             let s = serde_json::to_string(&result)?;
             Ok(s)
         }
@@ -219,8 +458,14 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
             let db_path = get_string_value(value, "db_path")?;
             let path_to_blend = get_string_value(value, "path_to_blend")?;
             let hash = get_string_value(value, "hash")?;
-
-            let result = restore_checkpoint(DBPath(db_path), PathToBlend(path_to_blend), hash)?;
+            let passphrase = get_optional_string_value(value, "passphrase");
+
+            let result = restore_checkpoint(
+                DBPath(db_path),
+                PathToBlend(path_to_blend),
+                hash,
+                passphrase,
+            )?;
             let s = serde_json::to_string(&result)?;
             Ok(s)
         }
@@ -238,9 +483,14 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
             let db_path = get_string_value(value, "db_path")?;
             let path_to_blend = get_string_value(value, "path_to_blend")?;
             let branch_name = get_string_value(value, "branch_name")?;
+            let passphrase = get_optional_string_value(value, "passphrase");
 
-            let result =
-                switch_to_branch(DBPath(db_path), PathToBlend(path_to_blend), branch_name)?;
+            let result = switch_to_branch(
+                DBPath(db_path),
+                PathToBlend(path_to_blend),
+                branch_name,
+                passphrase,
+            )?;
 
             let s = serde_json::to_string(&result)?;
             Ok(s)
@@ -248,7 +498,8 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
 
         "blend-file-from-timeline" => {
             let db_path = get_string_value(value, "db_path")?;
-            let result = blend_file_from_timeline(DBPath(db_path))?;
+            let passphrase = get_optional_string_value(value, "passphrase");
+            let result = blend_file_from_timeline(DBPath(db_path), passphrase)?;
             let s = serde_json::to_string(&result)?;
             Ok(s)
         }
@@ -261,6 +512,80 @@ pub fn do_command(value: Value) -> anyhow::Result<String> {
             Ok(s)
         }
 
+        "merge-branch" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let source_branch = get_string_value(value, "source_branch")?;
+            let target_branch = get_string_value(value, "target_branch")?;
+            let result = merge_branches(DBPath(db_path), source_branch, target_branch)?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "branch-history" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let result = get_branch_history(DBPath(db_path))?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "gc" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let keep_newer_than = get_optional_string_value(value, "keep_newer_than");
+            let result = run_gc(DBPath(db_path), keep_newer_than)?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "diff-checkpoints" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let from_hash = get_string_value(value, "from_hash")?;
+            let to_hash = get_string_value(value, "to_hash")?;
+            let result = run_diff_checkpoints(DBPath(db_path), from_hash, to_hash)?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "migrate" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let result = run_migrate(DBPath(db_path))?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "bisect-start" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let path_to_blend = get_string_value(value, "path_to_blend")?;
+            let good_hash = get_string_value(value, "good_hash")?;
+            let bad_hash = get_string_value(value, "bad_hash")?;
+            let passphrase = get_optional_string_value(value, "passphrase");
+
+            let result = bisect_start(
+                DBPath(db_path),
+                PathToBlend(path_to_blend),
+                good_hash,
+                bad_hash,
+                passphrase,
+            )?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
+        "bisect-step" => {
+            let db_path = get_string_value(value, "db_path")?;
+            let path_to_blend = get_string_value(value, "path_to_blend")?;
+            let verdict = get_string_value(value, "verdict")?;
+            let passphrase = get_optional_string_value(value, "passphrase");
+
+            let result = bisect_step(
+                DBPath(db_path),
+                PathToBlend(path_to_blend),
+                verdict,
+                passphrase,
+            )?;
+            let s = serde_json::to_string(&result)?;
+            Ok(s)
+        }
+
         c => {
             let resp = serde_json::to_string(&error_json(FFIError::InternalError(format!(
                 "Command {} not implemented",